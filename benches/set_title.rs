@@ -0,0 +1,41 @@
+//! Compares `set_title` with the unchecked `set_title_raw` variants, as when
+//! updating a packet counter in the title thousands of times a second.
+
+#[cfg(any(unix, windows))]
+use criterion::{black_box, criterion_group, criterion_main, Criterion};
+
+#[cfg(any(unix, windows))]
+fn set_title(c: &mut Criterion) {
+    c.bench_function("set_title", |b| {
+        b.iter(|| proctitle::set_title(black_box("1048576 packets")))
+    });
+}
+
+#[cfg(unix)]
+fn set_title_raw(c: &mut Criterion) {
+    let title = std::ffi::CStr::from_bytes_with_nul(b"1048576 packets\0").unwrap();
+    c.bench_function("set_title_raw", |b| {
+        b.iter(|| proctitle::set_title_raw(black_box(title)))
+    });
+}
+
+#[cfg(windows)]
+fn set_title_raw(c: &mut Criterion) {
+    use std::os::windows::ffi::OsStrExt;
+
+    let title: Vec<u16> = std::ffi::OsStr::new("1048576 packets\0")
+        .encode_wide()
+        .collect();
+    c.bench_function("set_title_raw_wide", |b| {
+        b.iter(|| proctitle::set_title_raw_wide(black_box(&title)))
+    });
+}
+
+#[cfg(any(unix, windows))]
+criterion_group!(benches, set_title, set_title_raw);
+#[cfg(any(unix, windows))]
+criterion_main!(benches);
+
+// Criterion needs threads and a clock, so there's nothing to run elsewhere.
+#[cfg(not(any(unix, windows)))]
+fn main() {}
@@ -1,5 +1,3 @@
-use proctitle;
-
 fn main() {
     let mut i = 0;
     loop {
@@ -0,0 +1,77 @@
+//! Feed arbitrary titles through the setters, checking what comes back.
+//!
+//! Each run shares a process, so the argv region and environment relocation
+//! on Linux and macOS see titles of every length in every order.
+//!
+//! ```sh
+//! cargo +nightly fuzz run set_title
+//! ```
+
+#![no_main]
+
+use std::ffi::OsStr;
+
+use libfuzzer_sys::fuzz_target;
+
+fuzz_target!(|data: &[u8]| {
+    let (&op, title) = match data.split_first() {
+        Some(split) => split,
+        None => return,
+    };
+
+    let title = os_str(title);
+    let result = match op % 4 {
+        0 => proctitle::set_title_checked(title),
+        1 => {
+            proctitle::set_title_sanitized(title);
+            return;
+        }
+        2 => {
+            proctitle::set_title(title);
+            return;
+        }
+        _ => {
+            proctitle::reset_title();
+            return;
+        }
+    };
+
+    let nul = title_bytes(title).contains(&0);
+    match result {
+        Err(proctitle::TitleError::InteriorNul) => assert!(nul),
+        _ => assert!(!nul, "title with a NUL accepted"),
+    }
+
+    // Whatever made it in must be the start of what we asked for.
+    #[cfg(any(target_os = "linux", target_os = "macos"))]
+    {
+        let set = matches!(
+            result,
+            Ok(()) | Err(proctitle::TitleError::Truncated { .. })
+        );
+        if set && !title.is_empty() {
+            let shown = proctitle::get_title().expect("title");
+            assert!(title_bytes(title).starts_with(title_bytes(&shown)));
+        }
+    }
+});
+
+#[cfg(unix)]
+fn os_str(bytes: &[u8]) -> &OsStr {
+    use std::os::unix::ffi::OsStrExt;
+    OsStr::from_bytes(bytes)
+}
+
+#[cfg(not(unix))]
+fn os_str(bytes: &[u8]) -> &OsStr {
+    // Only UTF-8 makes a guaranteed-valid OsStr elsewhere, so trim to that.
+    let valid = match std::str::from_utf8(bytes) {
+        Ok(s) => s,
+        Err(e) => std::str::from_utf8(&bytes[..e.valid_up_to()]).unwrap(),
+    };
+    OsStr::new(valid)
+}
+
+fn title_bytes(title: &OsStr) -> &[u8] {
+    title.as_encoded_bytes()
+}
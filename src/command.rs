@@ -0,0 +1,98 @@
+use std::ffi::{CString, OsStr};
+use std::os::unix::ffi::OsStrExt;
+use std::process::Command;
+
+mod private {
+    pub trait Sealed {}
+    impl Sealed for std::process::Command {}
+}
+
+/// Name the processes started by a [`Command`].
+///
+/// ```no_run
+/// use proctitle::CommandExt;
+/// use std::process::Command;
+///
+/// Command::new("myapp-worker").proc_title("myapp: worker 3").spawn().unwrap();
+/// ```
+///
+/// This trait is sealed, and only implemented for [`Command`].
+pub trait CommandExt: private::Sealed {
+    /// Set the child's title with [`set_title_signal_safe`] from a
+    /// [`pre_exec`] hook, after it's forked and before it runs the program,
+    /// as a supervisor names its children.
+    ///
+    /// That only lasts until the `exec()`: the kernel then names the process
+    /// after the executable's file name, and gives it the command line it was
+    /// started with, which this leaves alone.  Until then, such as while later
+    /// `pre_exec` hooks run or if the program can't be started, the child
+    /// shows up under `title` rather than as a copy of its parent.
+    ///
+    /// Titles containing NUL bytes are ignored, as are any errors setting the
+    /// title, so the child always goes on to start.
+    ///
+    /// [`set_title_signal_safe`]: crate::set_title_signal_safe
+    /// [`pre_exec`]: std::os::unix::process::CommandExt::pre_exec
+    fn proc_title<T: AsRef<OsStr>>(&mut self, title: T) -> &mut Self;
+}
+
+impl CommandExt for Command {
+    fn proc_title<T: AsRef<OsStr>>(&mut self, title: T) -> &mut Self {
+        if cfg!(feature = "disabled") {
+            return self;
+        }
+
+        // Built here, as the hook itself mustn't allocate.
+        let title = match CString::new(title.as_ref().as_bytes()) {
+            Ok(title) => title,
+            Err(_) => return self,
+        };
+
+        // Safety: set_title_signal_safe() only does what's async-signal-safe,
+        // which is all that's allowed between fork() and exec().
+        unsafe {
+            std::os::unix::process::CommandExt::pre_exec(self, move || {
+                let _ = crate::set_title_signal_safe(&title);
+                Ok(())
+            })
+        }
+    }
+}
+
+#[cfg(all(
+    not(any(feature = "disabled", feature = "mock")),
+    any(target_os = "linux", target_os = "android")
+))]
+#[test]
+fn proc_title_names_child_before_exec() {
+    use std::io::Read;
+    use std::os::unix::io::FromRawFd;
+
+    let mut fds = [0; 2];
+    assert_eq!(unsafe { libc::pipe2(fds.as_mut_ptr(), libc::O_CLOEXEC) }, 0);
+    let (reader, writer) = (fds[0], fds[1]);
+
+    // A later hook passes the child's name back to us before it's replaced.
+    let mut command = Command::new("true");
+    command.proc_title("worker 3: idle");
+    unsafe {
+        std::os::unix::process::CommandExt::pre_exec(&mut command, move || {
+            let mut name = [0u8; crate::MAX_LINUX_COMM_LEN];
+            let fd = libc::open(b"/proc/self/comm\0".as_ptr().cast(), libc::O_RDONLY);
+            let len = libc::read(fd, name.as_mut_ptr().cast(), name.len());
+            libc::close(fd);
+            libc::write(writer, name.as_ptr().cast(), len.max(0) as usize);
+            Ok(())
+        });
+    }
+
+    let status = command.status().unwrap();
+    unsafe { libc::close(writer) };
+    assert!(status.success());
+
+    let mut name = Vec::new();
+    unsafe { std::fs::File::from_raw_fd(reader) }
+        .read_to_end(&mut name)
+        .unwrap();
+    assert_eq!(name, b"worker 3: idle\n");
+}
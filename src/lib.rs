@@ -1,157 +1,5656 @@
+#![cfg_attr(not(feature = "std"), no_std)]
 #![cfg_attr(feature = "nightly", feature(external_doc))]
 #![cfg_attr(feature = "nightly", doc(include = "../README.md"))]
 
-#[cfg(any(
-    target_os = "freebsd",
-    target_os = "dragonfly",
-    target_os = "netbsd",
-    target_os = "openbsd",
-    target_os = "bitrig",
+#[cfg(all(
+    feature = "std",
+    not(any(feature = "disabled", feature = "mock")),
+    any(
+        target_os = "freebsd",
+        target_os = "dragonfly",
+        target_os = "netbsd",
+        target_os = "openbsd",
+        target_os = "bitrig",
+    )
 ))]
 mod imp {
-    use std::ffi::CString;
-    use std::ffi::OsStr;
-    use std::os::unix::ffi::OsStrExt;
+    use std::cell::RefCell;
+    use std::ffi::{CStr, CString};
+    use std::ffi::{OsStr, OsString};
+    use std::os::unix::ffi::{OsStrExt, OsStringExt};
+    use std::sync::atomic::{AtomicBool, Ordering};
+
+    use crate::TitleError;
+
+    pub const SUPPORTED: bool = true;
+
+    pub fn mechanism() -> crate::Mechanism {
+        crate::Mechanism::Setproctitle
+    }
+
+    static PREFIX: AtomicBool = AtomicBool::new(false);
+
+    pub fn set_prefix_enabled(enabled: bool) {
+        PREFIX.store(enabled, Ordering::Relaxed);
+    }
+
+    /// `title` as setproctitle() shows it, with any "progname: " prefix.
+    pub fn rendered(title: &OsStr) -> OsString {
+        let mut rendered = Vec::new();
+        if PREFIX.load(Ordering::Relaxed) {
+            rendered.extend_from_slice(unsafe { CStr::from_ptr(libc::getprogname()) }.to_bytes());
+            rendered.extend_from_slice(b": ");
+        }
+        rendered.extend_from_slice(title.as_bytes());
+        OsString::from_vec(rendered)
+    }
+
+    /// The format to hand setproctitle(), where a leading "-" suppresses its
+    /// "progname: " prefix.
+    ///
+    /// Titles are only ever passed as the argument to its %s, never as the
+    /// format itself, so any % in them is shown as-is rather than read as a
+    /// conversion.
+    fn format() -> *const u8 {
+        match PREFIX.load(Ordering::Relaxed) {
+            true => b"%s\0".as_ptr(),
+            false => b"-%s\0".as_ptr(),
+        }
+    }
+
+    /// The size of the buffer setproctitle() formats into, NUL included, and
+    /// so the limit on titles.  It's SPT_BUFSIZE in FreeBSD and DragonFly's
+    /// libc and MAX_PROCTITLE in NetBSD and OpenBSD's.  None of them expose it
+    /// through sysctl: NetBSD's kern.ps_strings only says where the title
+    /// lives, and the kernel reads back whatever's there, so this is the only
+    /// limit there is.
+    const MAX_PROCTITLE: usize = 2048;
+
+    pub fn max_title_len() -> Option<usize> {
+        // The buffer must also fit any "progname: " prefix.
+        let prefix = match PREFIX.load(Ordering::Relaxed) {
+            true => {
+                unsafe { CStr::from_ptr(libc::getprogname()) }
+                    .to_bytes()
+                    .len()
+                    + 2
+            }
+            false => 0,
+        };
+        Some(MAX_PROCTITLE - 1 - prefix)
+    }
+
+    pub fn set_title(title: &OsStr) -> Result<(), TitleError> {
+        let title = title.as_bytes();
+        with_cstr(title, set_title_raw)?;
+        check_truncation(title)
+    }
+
+    thread_local! {
+        // Scratch space for NUL-terminating titles, so frequent updates don't
+        // keep going back to the allocator.  Clippy doesn't see the const on
+        // OpenBSD, where thread locals are emulated.
+        #[allow(clippy::missing_const_for_thread_local)]
+        static TITLE_BUF: RefCell<Vec<u8>> = const { RefCell::new(Vec::new()) };
+    }
+
+    /// Run `f` with `title` NUL-terminated in this thread's scratch buffer.
+    fn with_cstr<F>(title: &[u8], f: F) -> Result<(), TitleError>
+    where
+        F: FnOnce(&CStr) -> Result<(), TitleError>,
+    {
+        TITLE_BUF.with(|buf| {
+            let mut buf = buf.borrow_mut();
+            buf.clear();
+            buf.extend_from_slice(title);
+            buf.push(0);
+            f(CStr::from_bytes_with_nul(&buf).map_err(|_| TitleError::InteriorNul)?)
+        })
+    }
+
+    pub fn set_title_raw(title: &CStr) -> Result<(), TitleError> {
+        unsafe {
+            setproctitle(format(), title.as_ptr());
+        }
+        #[cfg(any(target_os = "openbsd", target_os = "bitrig"))]
+        remember(title.to_bytes());
+        Ok(())
+    }
+
+    /// What we last handed setproctitle(), as `ps` will show it, or `None` for
+    /// the original arguments.
+    ///
+    /// Reading our arguments back needs sysctl(KERN_PROC_ARGS), which
+    /// pledge(2) only allows with the "ps" promise and answers with SIGABRT
+    /// otherwise.  setproctitle() itself gets by with "stdio", so keeping
+    /// track ourselves means a process pledged to just that can still use
+    /// everything here.  Titles set by anything else won't be seen.
+    #[cfg(any(target_os = "openbsd", target_os = "bitrig"))]
+    static CURRENT: std::sync::Mutex<Option<Vec<u8>>> = std::sync::Mutex::new(None);
+
+    #[cfg(any(target_os = "openbsd", target_os = "bitrig"))]
+    fn remember(title: &[u8]) {
+        let mut current = CURRENT.lock().unwrap_or_else(|e| e.into_inner());
+        let current = current.get_or_insert_with(Vec::new);
+        current.clear();
+        if PREFIX.load(Ordering::Relaxed) {
+            current.extend_from_slice(unsafe { CStr::from_ptr(libc::getprogname()) }.to_bytes());
+            current.extend_from_slice(b": ");
+        }
+        current.extend_from_slice(title);
+        current.truncate(MAX_PROCTITLE - 1);
+    }
+
+    fn check_truncation(title: &[u8]) -> Result<(), TitleError> {
+        let max = max_title_len().unwrap_or(usize::MAX);
+        crate::check_truncation(title.len().min(max), title.len())
+    }
+
+    pub fn reset_title() -> Result<(), TitleError> {
+        // A NULL format restores the original command line.
+        unsafe {
+            setproctitle(std::ptr::null());
+        }
+        #[cfg(any(target_os = "openbsd", target_os = "bitrig"))]
+        {
+            *CURRENT.lock().unwrap_or_else(|e| e.into_inner()) = None;
+        }
+        Ok(())
+    }
+
+    pub fn set_title_signal_safe(_title: &CStr) -> Result<(), TitleError> {
+        // setproctitle() formats into a buffer allocated on first use.
+        Err(TitleError::Unsupported)
+    }
 
-    /// Set a process title, or some approximation of it, if possible.
-    pub fn set_title<T: AsRef<OsStr>>(title: T) {
-        if let Ok(title) = CString::new(title.as_ref().to_owned().as_bytes()) {
+    #[cfg(target_os = "freebsd")]
+    pub fn set_title_fast(title: &OsStr) -> Result<(), TitleError> {
+        let title = title.as_bytes();
+        with_cstr(title, |title| {
             unsafe {
-                setproctitle(b"-%s\0".as_ptr(), title.as_ptr());
+                setproctitle_fast(format(), title.as_ptr());
+            }
+            Ok(())
+        })?;
+        check_truncation(title)
+    }
+
+    pub fn set_thread_title(title: &OsStr) -> Result<(), TitleError> {
+        let title = CString::new(title.as_bytes())?;
+
+        #[cfg(not(target_os = "netbsd"))]
+        unsafe {
+            libc::pthread_set_name_np(libc::pthread_self(), title.as_ptr());
+        }
+
+        // NetBSD rejects names longer than PTHREAD_MAX_NAMELEN_NP rather than
+        // truncating them.
+        #[cfg(target_os = "netbsd")]
+        unsafe {
+            let title = CString::new(&title.as_bytes()[..title.as_bytes().len().min(31)])?;
+            let ret = libc::pthread_setname_np(
+                libc::pthread_self(),
+                b"%s\0".as_ptr() as *const libc::c_char,
+                title.as_ptr() as *mut libc::c_void,
+            );
+            if ret != 0 {
+                return Err(std::io::Error::from_raw_os_error(ret).into());
+            }
+        }
+
+        Ok(())
+    }
+
+    pub fn get_thread_title() -> Option<OsString> {
+        None
+    }
+
+    #[cfg(any(target_os = "openbsd", target_os = "bitrig"))]
+    pub fn get_title() -> Option<OsString> {
+        let current = CURRENT.lock().unwrap_or_else(|e| e.into_inner());
+        let title = match current.as_ref() {
+            Some(title) => title.clone(),
+            None => {
+                let mut buf = Vec::new();
+                for arg in std::env::args_os() {
+                    buf.extend_from_slice(arg.as_bytes());
+                    buf.push(0);
+                }
+                join_args(&buf)
+            }
+        };
+        Some(OsString::from_vec(title))
+    }
+
+    #[cfg(not(any(target_os = "openbsd", target_os = "bitrig")))]
+    pub fn get_title() -> Option<OsString> {
+        let pid = unsafe { libc::getpid() };
+
+        #[cfg(any(target_os = "freebsd", target_os = "dragonfly"))]
+        let mut mib = [libc::CTL_KERN, libc::KERN_PROC, libc::KERN_PROC_ARGS, pid];
+        #[cfg(target_os = "netbsd")]
+        let mut mib = [
+            libc::CTL_KERN,
+            libc::KERN_PROC_ARGS,
+            pid,
+            libc::KERN_PROC_ARGV,
+        ];
+
+        let mut len = 0;
+        let mut buf: Vec<u8> = Vec::new();
+
+        // The arguments can change between sizing the buffer and filling it,
+        // so retry if we come up short.
+        loop {
+            let ret = unsafe {
+                libc::sysctl(
+                    mib.as_mut_ptr(),
+                    mib.len() as u32,
+                    std::ptr::null_mut(),
+                    &mut len,
+                    std::ptr::null_mut(),
+                    0,
+                )
+            };
+            if ret != 0 {
+                return None;
+            }
+
+            buf.resize(len, 0);
+            let ret = unsafe {
+                libc::sysctl(
+                    mib.as_mut_ptr(),
+                    mib.len() as u32,
+                    buf.as_mut_ptr() as *mut libc::c_void,
+                    &mut len,
+                    std::ptr::null_mut(),
+                    0,
+                )
+            };
+            if ret == 0 {
+                buf.truncate(len);
+                break;
+            }
+            if std::io::Error::last_os_error().raw_os_error() != Some(libc::ENOMEM) {
+                return None;
             }
         }
+
+        Some(OsString::from_vec(join_args(&buf)))
+    }
+
+    /// Turn a sequence of NUL-terminated arguments into a single space-separated
+    /// title, matching how `ps` displays them.
+    fn join_args(buf: &[u8]) -> Vec<u8> {
+        let buf = match buf.iter().rposition(|&b| b != 0) {
+            Some(end) => &buf[..=end],
+            None => &[],
+        };
+
+        buf.iter().map(|&b| if b == 0 { b' ' } else { b }).collect()
     }
 
     #[link(name = "c")]
     extern "C" {
         fn setproctitle(fmt: *const u8, ...);
+        #[cfg(target_os = "freebsd")]
+        fn setproctitle_fast(fmt: *const u8, ...);
     }
-}
 
-#[cfg(target_os = "linux")]
-mod imp {
-    use libc;
-    use std::ffi::CString;
-    use std::ffi::OsStr;
-    use std::os::unix::ffi::OsStrExt;
+    #[test]
+    fn set_title_sets_proc_args() {
+        let _lock = crate::test_lock();
+        crate::set_title("Within the bounds of reason");
+        assert_eq!(get_title().unwrap(), "Within the bounds of reason");
+    }
+
+    #[test]
+    fn reset_title_restores_proc_args() {
+        let _lock = crate::test_lock();
+        crate::reset_title();
+        let original = get_title().unwrap();
+        crate::set_title("Out to lunch");
+        crate::reset_title();
+        assert_eq!(get_title().unwrap(), original);
+    }
+
+    #[test]
+    fn set_title_is_not_a_format_string() {
+        // The title must only ever be an argument to "-%s", never the format.
+        let _lock = crate::test_lock();
+        crate::set_title("100% %s %n %x %%");
+        assert_eq!(get_title().unwrap(), "100% %s %n %x %%");
+
+        crate::set_title_fmt(format_args!("{}% done", 100));
+        assert_eq!(get_title().unwrap(), "100% done");
 
-    /// Set a process title, or some approximation of it, if possible.
-    pub fn set_title<T: AsRef<OsStr>>(title: T) {
-        if let Ok(title) = CString::new(title.as_ref().to_owned().as_bytes()) {
-            unsafe { libc::prctl(libc::PR_SET_NAME, title.as_ptr(), 0, 0, 0) };
+        crate::bsd_prefix(true);
+        crate::set_title("rate: 5%/s");
+        crate::bsd_prefix(false);
+        let progname = unsafe { CStr::from_ptr(libc::getprogname()) };
+        assert_eq!(
+            get_title().unwrap(),
+            &format!("{}: rate: 5%/s", progname.to_str().unwrap())[..]
+        );
+
+        #[cfg(target_os = "freebsd")]
+        {
+            crate::set_title_fast("100% %s");
+            assert_eq!(get_title().unwrap(), "100% %s");
         }
     }
 
     #[test]
-    fn set_title_sets_name() {
-        use libc;
-        set_title("abcdefghijklmnopqrstu");
+    fn bsd_prefix_adds_progname() {
+        let _lock = crate::test_lock();
+        let progname = unsafe { CStr::from_ptr(libc::getprogname()) };
+        let progname = progname.to_str().unwrap();
 
-        let mut buf = [0u8; 16];
-        unsafe { libc::prctl(libc::PR_GET_NAME, buf.as_mut_ptr(), 0, 0, 0) };
-        assert_eq!(&buf, b"abcdefghijklmno\0");
+        crate::bsd_prefix(true);
+        crate::set_title("prefixed");
+        crate::bsd_prefix(false);
+        assert_eq!(get_title().unwrap(), &format!("{}: prefixed", progname)[..]);
+
+        crate::set_title("unprefixed");
+        assert_eq!(get_title().unwrap(), "unprefixed");
+    }
+
+    #[test]
+    fn bsd_prefix_isnt_restored_twice() {
+        // setproctitle()'s prefix is read back, but mustn't be saved with the
+        // title and handed back to it to prefix again.
+        let _lock = crate::test_lock();
+        let progname = unsafe { CStr::from_ptr(libc::getprogname()) };
+        let expected = format!("{}: a", progname.to_str().unwrap());
+
+        crate::bsd_prefix(true);
+        crate::set_title("a");
+        drop(crate::set_title_scoped("b"));
+        let scoped = get_title().unwrap();
+        let replaced = crate::replace_title("c");
+        crate::bsd_prefix(false);
+
+        assert_eq!(scoped, &expected[..]);
+        assert_eq!(replaced.unwrap(), "a");
+        crate::reset_title();
+    }
+
+    #[cfg(target_os = "dragonfly")]
+    #[test]
+    fn dragonfly_dash_suppresses_prefix() {
+        // DragonFly's setproctitle() is FreeBSD's, from before the split, so
+        // a leading "-" should skip the "progname: " prefix just the same.
+        let _lock = crate::test_lock();
+        let progname = unsafe { CStr::from_ptr(libc::getprogname()) };
+        let progname = progname.to_str().unwrap();
+
+        unsafe { setproctitle(b"-%s\0".as_ptr(), b"bare\0".as_ptr()) };
+        assert_eq!(get_title().unwrap(), "bare");
+
+        unsafe { setproctitle(b"%s\0".as_ptr(), b"named\0".as_ptr()) };
+        assert_eq!(get_title().unwrap(), &format!("{}: named", progname)[..]);
+
+        unsafe { setproctitle(b"-%s\0".as_ptr(), b"-dashed\0".as_ptr()) };
+        assert_eq!(get_title().unwrap(), "-dashed");
+    }
+
+    #[test]
+    fn set_title_truncates_huge_titles() {
+        let _lock = crate::test_lock();
+        let max = max_title_len().unwrap();
+        let title = "x".repeat(64 * 1024);
+
+        match crate::set_title_checked(&title) {
+            Err(TitleError::Truncated { written, requested }) => {
+                assert_eq!((written, requested), (max, title.len()));
+            }
+            other => panic!("unexpected result: {:?}", other),
+        }
+
+        // What the kernel hands back should be exactly what we said would fit.
+        let got = get_title().unwrap().into_vec();
+        assert_eq!(got.len(), max);
+        assert!(got.iter().all(|&b| b == b'x'), "title corrupted");
+    }
+
+    #[test]
+    fn set_title_fast_sets_proc_args() {
+        let _lock = crate::test_lock();
+        crate::set_title_fast("Faster than a speeding bullet");
+        assert_eq!(get_title().unwrap(), "Faster than a speeding bullet");
+    }
+}
+
+#[cfg(all(
+    feature = "std",
+    not(any(feature = "disabled", feature = "mock")),
+    any(
+        target_os = "linux",
+        target_os = "android",
+        target_os = "macos",
+        target_os = "solaris",
+        target_os = "illumos",
+        target_os = "hurd"
+    )
+))]
+mod argv {
+    use std::os::raw::c_char;
+    use std::sync::atomic::{AtomicPtr, AtomicUsize, Ordering};
+
+    // A copy of the region's bounds for write_signal_safe(), which can't take
+    // the locks guarding the region itself.  There's only ever one region.
+    static SIGNAL_START: AtomicPtr<u8> = AtomicPtr::new(std::ptr::null_mut());
+    static SIGNAL_LEN: AtomicUsize = AtomicUsize::new(0);
+
+    /// Overwrite the region, as [`ArgvRegion::write`], without allocating or
+    /// locking, returning false if there's no region yet.
+    ///
+    /// This may race with other writes, garbling the title, but never strays
+    /// outside the region.
+    pub unsafe fn write_signal_safe(title: &[u8]) -> bool {
+        let start = SIGNAL_START.load(Ordering::Acquire);
+        let len = SIGNAL_LEN.load(Ordering::Acquire);
+        if start.is_null() || len == 0 {
+            return false;
+        }
+
+        let title = &title[..title.len().min(len - 1)];
+        std::ptr::copy_nonoverlapping(title.as_ptr(), start, title.len());
+        std::ptr::write_bytes(start.add(title.len()), 0, len - title.len());
+        true
+    }
+
+    /// The contiguous block of memory the kernel originally laid our argv
+    /// strings out in, which is where `ps` and friends look for them.
+    pub struct ArgvRegion {
+        start: *mut u8,
+        len: usize,
+        original: Vec<u8>,
+        // The process `original` was captured in.
+        pid: libc::pid_t,
+    }
+    unsafe impl Send for ArgvRegion {}
+
+    impl ArgvRegion {
+        /// Claim the region at `start` without touching argv itself.
+        ///
+        /// The memory must be ours to overwrite.
+        pub unsafe fn new(start: *mut u8, len: usize) -> Option<Self> {
+            if start.is_null() || len == 0 {
+                return None;
+            }
+
+            let original = std::slice::from_raw_parts(start, len).to_vec();
+            SIGNAL_LEN.store(len, Ordering::Release);
+            SIGNAL_START.store(start, Ordering::Release);
+            Some(Self {
+                start,
+                len,
+                original,
+                pid: libc::getpid(),
+            })
+        }
+
+        /// Locate the argv strings and move them out of the way so std::env::args()
+        /// and friends keep working once we start scribbling over the original.
+        pub unsafe fn take(argc: usize, argv: *mut *mut c_char) -> Option<Self> {
+            if argc < 1 || argv.is_null() || (*argv).is_null() {
+                return None;
+            }
+
+            let start = (*argv).cast::<u8>();
+            let mut end = start;
+
+            for i in 0..argc {
+                let arg = (*argv.add(i)).cast::<u8>();
+                if arg != end {
+                    break;
+                }
+
+                // Out of memory, leave this string and the rest where they are,
+                // outside the region.
+                let copy = libc::strdup(arg as *const c_char);
+                if copy.is_null() {
+                    break;
+                }
+
+                let len = libc::strlen(arg as *const c_char) + 1;
+                *argv.add(i) = copy;
+                end = arg.add(len);
+            }
+
+            Self::new(start, end as usize - start as usize)
+        }
+
+        /// The size of the region in bytes, including space for a trailing NUL.
+        pub fn len(&self) -> usize {
+            self.len
+        }
+
+        /// The address just past the end of the region.
+        #[cfg(any(target_os = "linux", target_os = "android"))]
+        pub fn end(&self) -> usize {
+            self.start as usize + self.len
+        }
+
+        /// Grow the region to end at `end`.
+        ///
+        /// The extra memory must be ours to overwrite.
+        #[cfg(any(target_os = "linux", target_os = "android"))]
+        pub unsafe fn extend_to(&mut self, end: usize) {
+            if end > self.end() {
+                self.len = end - self.start as usize;
+                SIGNAL_LEN.store(self.len, Ordering::Release);
+            }
+        }
+
+        /// The contents the region had when we claimed it.
+        #[cfg(any(target_os = "linux", target_os = "android"))]
+        pub fn original(&self) -> &[u8] {
+            &self.original
+        }
+
+        /// Read back the region as a single space-separated title, matching how
+        /// `ps` would display it.
+        pub fn read(&self) -> Vec<u8> {
+            let buf = unsafe { std::slice::from_raw_parts(self.start, self.len) };
+            let buf = match buf.iter().rposition(|&b| b != 0) {
+                Some(end) => &buf[..=end],
+                None => &[],
+            };
+
+            buf.iter().map(|&b| if b == 0 { b' ' } else { b }).collect()
+        }
+
+        /// Put back the contents the region had when we claimed it, or when we
+        /// were forked from the process that did.
+        pub fn restore(&mut self) {
+            self.recapture_after_fork();
+            let original = std::mem::take(&mut self.original);
+            self.overwrite(&original);
+            self.original = original;
+        }
+
+        /// Overwrite the region with `title`, truncating it to fit and
+        /// NUL-padding any remaining space, returning how many bytes fit.
+        pub fn write(&mut self, title: &[u8]) -> usize {
+            self.recapture_after_fork();
+            self.overwrite(title)
+        }
+
+        /// A forked child inherits the region along with the rest of memory,
+        /// but the title it starts out with is whatever the parent had at the
+        /// time, so take that as its original instead.
+        fn recapture_after_fork(&mut self) {
+            let pid = unsafe { libc::getpid() };
+            if pid != self.pid {
+                self.pid = pid;
+                self.original =
+                    unsafe { std::slice::from_raw_parts(self.start, self.len) }.to_vec();
+            }
+        }
+
+        fn overwrite(&mut self, title: &[u8]) -> usize {
+            let title = &title[..title.len().min(self.len - 1)];
+
+            unsafe {
+                std::ptr::copy_nonoverlapping(title.as_ptr(), self.start, title.len());
+                std::ptr::write_bytes(self.start.add(title.len()), 0, self.len - title.len());
+            }
+
+            title.len()
+        }
     }
 }
 
-#[cfg(target_os = "windows")]
+#[cfg(all(
+    feature = "std",
+    not(any(feature = "disabled", feature = "mock")),
+    any(target_os = "linux", target_os = "android")
+))]
 mod imp {
-    use std::ffi::OsStr;
-    use std::os::windows::ffi::OsStrExt;
-    use std::sync::Mutex;
+    use std::ffi::{CStr, OsStr, OsString};
+    use std::os::raw::c_char;
+    use std::os::unix::ffi::{OsStrExt, OsStringExt};
+    use std::sync::atomic::{AtomicBool, AtomicU8, Ordering};
+    use std::sync::{Mutex, MutexGuard};
 
     use lazy_static::lazy_static;
-    use winapi::um::handleapi::CloseHandle;
-    use winapi::um::synchapi::CreateEventW;
-    use winapi::um::wincon::SetConsoleTitleW;
-    use winapi::um::winnt::HANDLE;
 
-    struct NamedHandle(HANDLE);
-    unsafe impl Send for NamedHandle {}
+    use crate::argv::ArgvRegion;
+    use crate::{LinuxTitleMode, TitleError, MAX_LINUX_COMM_LEN};
+
+    pub const SUPPORTED: bool = true;
+
+    pub fn mechanism() -> crate::Mechanism {
+        match CMDLINE.lock().expect("cmdline lock").is_some() {
+            true => crate::Mechanism::ArgvOverwrite,
+            false => crate::Mechanism::Prctl,
+        }
+    }
+
+    extern "C" {
+        static mut environ: *mut *mut c_char;
+    }
+
+    /// The handful of fields we need from `/proc/self/stat`.
+    struct Stat {
+        start_stack: usize,
+        arg_start: usize,
+        arg_end: usize,
+        env_start: usize,
+        env_end: usize,
+    }
+
+    impl Stat {
+        fn read() -> Option<Self> {
+            let stat = std::fs::read("/proc/self/stat").ok()?;
+
+            // The command name may itself contain spaces and parentheses, so
+            // start counting fields after the last closing parenthesis.
+            let comm_end = stat.iter().rposition(|&b| b == b')')?;
+            let fields: Vec<&[u8]> = stat[comm_end + 1..]
+                .split(u8::is_ascii_whitespace)
+                .filter(|f| !f.is_empty())
+                .collect();
+
+            // Fields are numbered from 1 in proc(5), and we skipped the first two.
+            let field = |n: usize| -> Option<usize> {
+                std::str::from_utf8(fields.get(n - 3)?).ok()?.parse().ok()
+            };
+
+            Some(Self {
+                start_stack: field(28)?,
+                arg_start: field(48)?,
+                arg_end: field(49)?,
+                env_start: field(50)?,
+                env_end: field(51)?,
+            })
+        }
+    }
+
+    /// Find the memory the kernel reads our command line from.
+    ///
+    /// The kernel tells us where the argv strings live, and where the stack
+    /// started, which is where argc and the argv array itself sit.  If the
+    /// latter checks out we can copy the strings elsewhere and repoint argv
+    /// at them, so std::env::args() keeps working.
+    ///
+    /// Android may deny access to `/proc/self/stat`, or report zeros for these
+    /// fields, in which case we're limited to the 15-byte thread name.  So are
+    /// we if the memory turns out not to be writable, as it might be under a
+    /// hardened kernel or a sanitizer, rather than crashing on the first title.
+    unsafe fn argv_region() -> Option<ArgvRegion> {
+        let stat = Stat::read()?;
+
+        if stat.arg_start == 0 || stat.arg_end <= stat.arg_start {
+            return None;
+        }
+
+        let start = match stat.start_stack {
+            0 => stat.arg_start,
+            start_stack => start_stack.min(stat.arg_start),
+        };
+        if !writable(start, stat.arg_end) {
+            return None;
+        }
+
+        if stat.start_stack != 0 {
+            let argc = *(stat.start_stack as *const usize);
+            let argv = (stat.start_stack as *mut *mut c_char).add(1);
+
+            if argc > 0 && *argv as usize == stat.arg_start {
+                return ArgvRegion::take(argc, argv);
+            }
+        }
+
+        ArgvRegion::new(stat.arg_start as *mut u8, stat.arg_end - stat.arg_start)
+    }
+
+    /// Whether all the memory from `start` to `end` is mapped writable,
+    /// according to `/proc/self/maps`, assuming not if it can't be read.
+    fn writable(start: usize, end: usize) -> bool {
+        let maps = match std::fs::read_to_string("/proc/self/maps") {
+            Ok(maps) => maps,
+            Err(_) => return false,
+        };
+
+        // Mappings are listed in order, as "start-end perms ...", in hex.
+        let mut pos = start;
+        for line in maps.lines() {
+            let mut fields = line.split_whitespace();
+            let (range, perms) = match (fields.next(), fields.next()) {
+                (Some(range), Some(perms)) => (range, perms),
+                _ => return false,
+            };
+            let (from, to) = match range.split_once('-').map(|(from, to)| {
+                (
+                    usize::from_str_radix(from, 16),
+                    usize::from_str_radix(to, 16),
+                )
+            }) {
+                Some((Ok(from), Ok(to))) => (from, to),
+                _ => return false,
+            };
+
+            if from <= pos && pos < to {
+                if perms.as_bytes().get(1) != Some(&b'w') {
+                    return false;
+                }
+                pos = to;
+                if pos >= end {
+                    return true;
+                }
+            }
+        }
+
+        false
+    }
+
+    /// Move any environment strings out of the memory directly following argv,
+    /// and grow the region to cover it, so longer titles can fit.
+    ///
+    /// As with argv, the strings are copied and `environ` repointed at them, so
+    /// later readers still see a valid environment.  This costs an allocation
+    /// per environment variable, so is deferred until a title doesn't fit in
+    /// argv alone.
+    unsafe fn expand_into_environ(region: &mut ArgvRegion) {
+        let stat = match Stat::read() {
+            Some(stat) => stat,
+            None => return,
+        };
+
+        if stat.env_start != region.end()
+            || stat.env_end <= stat.env_start
+            || environ.is_null()
+            || !writable(stat.env_start, stat.env_end)
+        {
+            return;
+        }
+
+        let mut env = environ;
+        while !(*env).is_null() {
+            let var = *env as usize;
+            if var >= stat.env_start && var < stat.env_end {
+                let copy = libc::strdup(*env);
+                if copy.is_null() {
+                    return;
+                }
+                *env = copy;
+            }
+            env = env.add(1);
+        }
+
+        region.extend_to(stat.env_end);
+    }
+
+    struct Cmdline {
+        region: ArgvRegion,
+        expanded: bool,
+        // The basename of the original argv[0], for the "progname: " prefix,
+        // and scratch space for putting it in front of titles.
+        progname: Vec<u8>,
+        prefixed: Vec<u8>,
+    }
+
+    impl Cmdline {
+        fn new(region: ArgvRegion) -> Self {
+            let argv0 = region.original().split(|&b| b == 0).next().unwrap_or(&[]);
+            let progname = match argv0.iter().rposition(|&b| b == b'/') {
+                Some(slash) => &argv0[slash + 1..],
+                None => argv0,
+            };
+
+            Self {
+                progname: progname.to_vec(),
+                region,
+                expanded: false,
+                prefixed: Vec::new(),
+            }
+        }
+
+        /// The length of the "progname: " prefix, if enabled.
+        fn prefix_len(&self) -> usize {
+            match PREFIX.load(Ordering::Relaxed) {
+                true => self.progname.len() + 2,
+                false => 0,
+            }
+        }
+
+        /// Write `title` over the command line, after the prefix if enabled,
+        /// returning how much of `title` fit.
+        fn write(&mut self, title: &[u8]) -> usize {
+            let prefix_len = self.prefix_len();
+            if !self.expanded && prefix_len + title.len() >= self.region.len() {
+                unsafe { expand_into_environ(&mut self.region) };
+                self.expanded = true;
+            }
 
-    impl From<Vec<u16>> for NamedHandle {
-        fn from(t: Vec<u16>) -> Self {
-            assert!(t.ends_with(&[0]));
+            if prefix_len == 0 {
+                return self.region.write(title);
+            }
 
-            Self(unsafe { CreateEventW(std::ptr::null_mut(), 1, 0, t.as_ptr()) })
+            self.prefixed.clear();
+            self.prefixed.extend_from_slice(&self.progname);
+            self.prefixed.extend_from_slice(b": ");
+            self.prefixed.extend_from_slice(title);
+            self.region.write(&self.prefixed).saturating_sub(prefix_len)
         }
     }
 
-    impl Drop for NamedHandle {
-        fn drop(&mut self) {
-            if !self.0.is_null() {
-                unsafe { CloseHandle(self.0) };
+    static PREFIX: AtomicBool = AtomicBool::new(false);
+
+    pub fn set_prefix_enabled(enabled: bool) {
+        PREFIX.store(enabled, Ordering::Relaxed);
+    }
+
+    /// `title` as get_title() reads it back, with any "progname: " prefix,
+    /// which the process name never has.
+    pub fn rendered(title: &OsStr) -> OsString {
+        let cmdline = CMDLINE.lock().expect("cmdline lock");
+        match cmdline.as_ref() {
+            Some(cmdline) if cmdline.prefix_len() > 0 && !COMM_ONLY.load(Ordering::Relaxed) => {
+                let mut rendered = cmdline.progname.clone();
+                rendered.extend_from_slice(b": ");
+                rendered.extend_from_slice(title.as_bytes());
+                OsString::from_vec(rendered)
             }
+            _ => title.to_owned(),
         }
     }
 
+    // lazy_static runs the initialiser exactly once, however many threads race
+    // on the first title, and the mutex keeps them from relocating the
+    // environment at the same time.
     lazy_static! {
-        static ref EVENT_HANDLE: Mutex<Option<NamedHandle>> = Mutex::new(None);
+        static ref CMDLINE: Mutex<Option<Cmdline>> =
+            Mutex::new(unsafe { argv_region() }.map(Cmdline::new));
+
+        // The main thread's name before we changed it, for reset_title(), and
+        // the process it was read in.
+        static ref ORIGINAL_COMM: Mutex<(libc::pid_t, Option<Vec<u8>>)> =
+            Mutex::new((unsafe { libc::getpid() }, read_comm()));
     }
 
-    /// Set a process title, or some approximation of it, if possible.
-    pub fn set_title<T: AsRef<OsStr>>(title: T) {
-        // Windows doesn't appear to have a userspace mechanism to name the current
-        // process.
-        //
-        // Try to set a console title, and in case we're not attached to one,
-        // follow PostgreSQL's lead and create a named event handle that can be
-        // found in Process Explorer, Process Hacker, etc.
-        let mut t: Vec<u16> = title.as_ref().encode_wide().take(1024).collect();
-        t.push(0);
+    // With the early-init feature, claim the command line before main(), in
+    // case anything else rearranges argv or the environment before our first
+    // title.  glibc, musl and bionic all run .init_array entries at load time.
+    #[cfg(feature = "early-init")]
+    #[used]
+    #[link_section = ".init_array"]
+    static EARLY_INIT: extern "C" fn() = early_init;
 
-        unsafe { SetConsoleTitleW(t.as_ptr()) };
+    #[cfg(feature = "early-init")]
+    static EARLY_INIT_RAN: AtomicBool = AtomicBool::new(false);
 
-        EVENT_HANDLE
-            .lock()
-            .expect("event handle lock")
-            .replace(NamedHandle::from(t));
+    #[cfg(feature = "early-init")]
+    extern "C" fn early_init() {
+        lazy_static::initialize(&CMDLINE);
+        drop(original_comm());
+        EARLY_INIT_RAN.store(true, Ordering::Relaxed);
     }
 
-    #[test]
-    fn set_title_sets_console_title_and_makes_a_handle() {
-        let title = "Pinkle, squirmy, blib, blab, blob";
-        set_title(title);
+    fn read_comm() -> Option<Vec<u8>> {
+        std::fs::read("/proc/self/comm")
+            .ok()
+            .map(|comm| comm.strip_suffix(b"\n").unwrap_or(&comm).to_vec())
+    }
 
-        let mut t: Vec<u16> = std::ffi::OsString::from(title).encode_wide().collect();
-        t.push(0);
-        let mut buf = vec![0; t.len()];
-        let len =
-            unsafe { winapi::um::wincon::GetConsoleTitleW(buf.as_mut_ptr(), buf.len() as u32) };
+    /// The main thread's original name, as ArgvRegion keeps the original
+    /// command line: re-read on the first use after a fork, so a child's
+    /// reset_title() puts back its own starting name.
+    fn original_comm() -> MutexGuard<'static, (libc::pid_t, Option<Vec<u8>>)> {
+        let mut comm = ORIGINAL_COMM.lock().expect("comm lock");
+        let pid = unsafe { libc::getpid() };
+        if comm.0 != pid {
+            *comm = (pid, read_comm());
+        }
+        comm
+    }
 
-        assert_eq!(len, title.len() as u32, "length mismatch");
-        assert_eq!(buf, t, "buffer mismatch");
-        assert!(
-            EVENT_HANDLE.lock().unwrap().is_some(),
-            "event handle missing"
+    static DEFAULT_MODE: AtomicU8 = AtomicU8::new(LinuxTitleMode::Both as u8);
+
+    pub fn set_default_mode(mode: LinuxTitleMode) {
+        DEFAULT_MODE.store(mode as u8, Ordering::Relaxed);
+    }
+
+    pub fn default_mode() -> LinuxTitleMode {
+        match DEFAULT_MODE.load(Ordering::Relaxed) {
+            m if m == LinuxTitleMode::Comm as u8 => LinuxTitleMode::Comm,
+            m if m == LinuxTitleMode::Argv as u8 => LinuxTitleMode::Argv,
+            _ => LinuxTitleMode::Both,
+        }
+    }
+
+    pub fn set_title(title: &OsStr) -> Result<(), TitleError> {
+        set_title_with_mode(default_mode(), title)
+    }
+
+    pub fn set_title_with_mode(mode: LinuxTitleMode, title: &OsStr) -> Result<(), TitleError> {
+        // This may well be called several times a second, so avoid allocating.
+        let title = title.as_bytes();
+
+        if title.contains(&0) {
+            return Err(TitleError::InteriorNul);
+        }
+
+        drop(original_comm());
+
+        // PR_SET_NAME only sets the 15-byte name shown by `top` and `ps -o comm`,
+        // so also overwrite argv so the full title appears in `ps` and
+        // /proc/self/cmdline, like setproctitle() on the BSDs.
+        let comm_len = comm_len(title);
+        let written = match mode {
+            LinuxTitleMode::Comm => comm_len,
+            _ => match CMDLINE.lock().expect("cmdline lock").as_mut() {
+                Some(cmdline) => cmdline.write(title),
+                None if mode == LinuxTitleMode::Argv => return Err(TitleError::Unsupported),
+                None => comm_len,
+            },
+        };
+
+        // Name the process after what made it into argv, so that whatever
+        // `pgrep` matches in the name, `pgrep -f` finds in the command line.
+        if mode != LinuxTitleMode::Argv {
+            set_process_name(&title[..written])?;
+        }
+        COMM_ONLY.store(
+            mode == LinuxTitleMode::Comm || CMDLINE.lock().expect("cmdline lock").is_none(),
+            Ordering::Relaxed,
         );
+        crate::check_truncation(written, title.len())
     }
-}
 
-#[cfg(not(any(
-    target_os = "freebsd",
-    target_os = "dragonfly",
-    target_os = "netbsd",
-    target_os = "openbsd",
-    target_os = "bitrig",
-    target_os = "linux",
-    target_os = "windows"
-)))]
-mod imp {
-    use std::ffi::OsStr;
+    // Whether the last title only went to the process name, leaving argv
+    // behind, so get_title() knows where to look.
+    static COMM_ONLY: AtomicBool = AtomicBool::new(false);
 
-    /// Set a process title, or some approximation of it, if possible.
-    pub fn set_title<T: AsRef<OsStr>>(_title: T) {}
-}
+    pub fn set_title_signal_safe(title: &CStr) -> Result<(), TitleError> {
+        let title = title.to_bytes();
+        unsafe { crate::argv::write_signal_safe(title) };
 
-pub use self::imp::*;
+        let name = comm_name(title);
+        let main_thread =
+            unsafe { libc::syscall(libc::SYS_gettid) == libc::getpid() as libc::c_long };
 
-// This races against the SetConsoleTitle() tests on Windows
-#[cfg(not(windows))]
-#[test]
-fn set_title_is_at_least_callable() {
-    set_title("What was it like being a hamster?");
-    set_title(String::from("It was better than being a chicken."));
-    set_title(std::ffi::OsString::from(
-        "Have you seen the size of an egg?",
-    ));
+        if main_thread {
+            return set_name(&name);
+        }
+
+        // As set_process_name(), but only using async-signal-safe calls.
+        unsafe {
+            let fd = libc::open(
+                b"/proc/self/comm\0".as_ptr() as *const c_char,
+                libc::O_WRONLY,
+            );
+            if fd < 0 {
+                return Err(std::io::Error::last_os_error().into());
+            }
+
+            let len = comm_len(title);
+            let ret = libc::write(fd, name.as_ptr() as *const libc::c_void, len);
+            let err = std::io::Error::last_os_error();
+            libc::close(fd);
+            if ret < 0 {
+                return Err(err.into());
+            }
+        }
+
+        Ok(())
+    }
+
+    pub fn reset_title() -> Result<(), TitleError> {
+        if let Some(cmdline) = CMDLINE.lock().expect("cmdline lock").as_mut() {
+            cmdline.region.restore();
+        }
+        COMM_ONLY.store(false, Ordering::Relaxed);
+
+        match original_comm().1.as_ref() {
+            Some(comm) => set_process_name(comm),
+            None => Ok(()),
+        }
+    }
+
+    /// How much of `title` fits the kernel's `TASK_COMM_LEN`, NUL included,
+    /// without splitting a UTF-8 character that would otherwise leave `top`
+    /// showing a replacement character.  Titles that aren't UTF-8 are simply
+    /// cut short.
+    fn comm_len(title: &[u8]) -> usize {
+        let len = title.len().min(MAX_LINUX_COMM_LEN - 1);
+        match std::str::from_utf8(&title[..len]) {
+            Err(e) if e.error_len().is_none() => e.valid_up_to(),
+            _ => len,
+        }
+    }
+
+    /// Truncate `title` to fit the kernel's `TASK_COMM_LEN`, which is all
+    /// it reads anyway.
+    fn comm_name(title: &[u8]) -> [u8; MAX_LINUX_COMM_LEN] {
+        let mut name = [0u8; MAX_LINUX_COMM_LEN];
+        let len = comm_len(title);
+        name[..len].copy_from_slice(&title[..len]);
+        name
+    }
+
+    /// Set the name of the main thread, which tools treat as the name of the
+    /// process as a whole.
+    fn set_process_name(title: &[u8]) -> Result<(), TitleError> {
+        let main_thread =
+            unsafe { libc::syscall(libc::SYS_gettid) == libc::getpid() as libc::c_long };
+
+        if main_thread {
+            set_name(&comm_name(title))
+        } else {
+            // PR_SET_NAME only ever names the calling thread, but any thread
+            // may rename others in the same process via procfs.
+            std::fs::write("/proc/self/comm", &title[..comm_len(title)])?;
+            Ok(())
+        }
+    }
+
+    fn set_name(name: &[u8; MAX_LINUX_COMM_LEN]) -> Result<(), TitleError> {
+        if unsafe { libc::prctl(libc::PR_SET_NAME, name.as_ptr(), 0, 0, 0) } != 0 {
+            return Err(std::io::Error::last_os_error().into());
+        }
+        Ok(())
+    }
+
+    pub fn set_thread_title(title: &OsStr) -> Result<(), TitleError> {
+        let title = title.as_bytes();
+
+        if title.contains(&0) {
+            return Err(TitleError::InteriorNul);
+        }
+
+        // Naming the main thread renames the process, too.
+        drop(original_comm());
+        set_name(&comm_name(title))
+    }
+
+    pub fn get_thread_title() -> Option<OsString> {
+        let mut buf = [0u8; MAX_LINUX_COMM_LEN];
+        if unsafe { libc::prctl(libc::PR_GET_NAME, buf.as_mut_ptr(), 0, 0, 0) } != 0 {
+            return None;
+        }
+
+        let len = buf.iter().position(|&b| b == 0).unwrap_or(buf.len());
+        Some(OsString::from_vec(buf[..len].to_vec()))
+    }
+
+    pub fn max_title_len() -> Option<usize> {
+        let cmdline = CMDLINE.lock().expect("cmdline lock");
+        let cmdline = match cmdline.as_ref() {
+            Some(cmdline) => cmdline,
+            None => return Some(MAX_LINUX_COMM_LEN - 1),
+        };
+
+        // Account for the space we'd gain by moving the environment.
+        let mut len = cmdline.region.len();
+        if !cmdline.expanded {
+            if let Some(stat) = Stat::read() {
+                if stat.env_start == cmdline.region.end() && stat.env_end > stat.env_start {
+                    len += stat.env_end - stat.env_start;
+                }
+            }
+        }
+
+        Some((len - 1).saturating_sub(cmdline.prefix_len()))
+    }
+
+    pub fn get_title() -> Option<OsString> {
+        // The full title is in argv, as `ps` shows, unless it was last left
+        // behind by LinuxTitleMode::Comm.
+        if !COMM_ONLY.load(Ordering::Relaxed) {
+            if let Some(cmdline) = CMDLINE.lock().expect("cmdline lock").as_ref() {
+                return Some(OsString::from_vec(cmdline.region.read()));
+            }
+        }
+
+        match read_comm() {
+            Some(comm) => Some(OsString::from_vec(comm)),
+            None => get_thread_title(),
+        }
+    }
+
+    #[cfg(test)]
+    fn thread_name() -> [u8; 16] {
+        let mut buf = [0u8; 16];
+        unsafe { libc::prctl(libc::PR_GET_NAME, buf.as_mut_ptr(), 0, 0, 0) };
+        buf
+    }
+
+    #[test]
+    fn set_title_sets_name() {
+        let _lock = crate::test_lock();
+        let name = thread_name();
+        crate::set_title("abcdefghijklmnopqrstu");
+
+        // Android shares the kernel's 16-byte TASK_COMM_LEN, NUL included.
+        assert_eq!(
+            std::fs::read("/proc/self/comm").unwrap(),
+            b"abcdefghijklmno\n"
+        );
+        assert_eq!(thread_name(), name, "test thread renamed");
+    }
+
+    #[test]
+    fn bsd_prefix_prefixes_cmdline() {
+        let _lock = crate::test_lock();
+        let argv0 = std::env::args_os().next().unwrap();
+        let progname = std::path::Path::new(&argv0).file_name().unwrap();
+        let max = crate::max_title_len().unwrap();
+
+        crate::bsd_prefix(true);
+        crate::set_title("prefixed");
+        let title = get_title().unwrap();
+        let prefixed_max = crate::max_title_len().unwrap();
+        crate::bsd_prefix(false);
+
+        let mut expected = progname.to_owned();
+        expected.push(": prefixed");
+        assert_eq!(title, expected);
+        assert_eq!(std::fs::read("/proc/self/comm").unwrap(), b"prefixed\n");
+        assert_eq!(prefixed_max, max - progname.len() - 2);
+    }
+
+    #[test]
+    fn bsd_prefix_isnt_restored_twice() {
+        let _lock = crate::test_lock();
+        let argv0 = std::env::args_os().next().unwrap();
+        let mut expected = std::path::Path::new(&argv0).file_name().unwrap().to_owned();
+        expected.push(": a");
+
+        crate::bsd_prefix(true);
+        crate::set_title("a");
+        drop(crate::set_title_scoped("b"));
+        let scoped = get_title().unwrap();
+        let replaced = crate::replace_title("c");
+        crate::bsd_prefix(false);
+
+        assert_eq!(scoped, expected);
+        assert_eq!(replaced.unwrap(), "a");
+        crate::reset_title();
+    }
+
+    #[test]
+    fn set_title_keeps_utf8_whole_in_comm() {
+        let _lock = crate::test_lock();
+        // "ü" is two bytes, straddling the 15-byte limit.
+        crate::set_title("abcdefghijklmnü");
+        assert_eq!(
+            std::fs::read("/proc/self/comm").unwrap(),
+            b"abcdefghijklmn\n"
+        );
+
+        crate::set_thread_title("abcdefghijklm€");
+        assert_eq!(&thread_name(), b"abcdefghijklm\0\0\0");
+
+        // Bytes that were never UTF-8 are left to the kernel.
+        crate::set_title(OsStr::from_bytes(b"abcdefghijklmn\xff\xff"));
+        assert_eq!(
+            std::fs::read("/proc/self/comm").unwrap(),
+            b"abcdefghijklmn\xff\n"
+        );
+    }
+
+    #[test]
+    fn set_thread_title_sets_thread_name() {
+        let _lock = crate::test_lock();
+        let comm = std::fs::read("/proc/self/comm").unwrap();
+        crate::set_thread_title("io-worker-3 with a long name");
+
+        assert_eq!(&thread_name(), b"io-worker-3 wit\0");
+        assert_eq!(
+            std::fs::read("/proc/self/comm").unwrap(),
+            comm,
+            "process renamed"
+        );
+    }
+
+    #[test]
+    fn get_thread_title_reads_thread_name() {
+        let name = std::thread::spawn(|| {
+            crate::set_thread_title("io-worker-3 with a long name");
+            crate::get_thread_title()
+        })
+        .join()
+        .unwrap();
+
+        assert_eq!(name.unwrap(), "io-worker-3 wit");
+    }
+
+    #[test]
+    fn set_title_rejects_nul_past_comm_len() {
+        let _lock = crate::test_lock();
+        let name = b"abcdefghijklmnopq\0rst";
+        let name = OsStr::from_bytes(name);
+
+        assert!(matches!(
+            crate::set_title_checked(name),
+            Err(TitleError::InteriorNul)
+        ));
+        assert!(matches!(
+            set_thread_title(name),
+            Err(TitleError::InteriorNul)
+        ));
+    }
+
+    #[test]
+    fn set_title_accepts_non_utf8_path() {
+        let _lock = crate::test_lock();
+        let path = std::path::Path::new(OsStr::from_bytes(b"/tmp/caf\xe9.txt"));
+        crate::set_title(path);
+
+        let cmdline = std::fs::read("/proc/self/cmdline").unwrap();
+        assert!(cmdline.starts_with(b"/tmp/caf\xe9.txt\0"));
+        assert_eq!(get_title().unwrap(), path.as_os_str());
+    }
+
+    #[test]
+    fn reset_title_restores_cmdline_and_name() {
+        let _lock = crate::test_lock();
+        let mut args = Vec::new();
+        for arg in std::env::args_os() {
+            args.extend_from_slice(arg.as_bytes());
+            args.push(0);
+        }
+        let exe = std::env::current_exe().unwrap();
+        let mut comm = exe.file_name().unwrap().as_bytes().to_vec();
+        comm.truncate(15);
+        comm.push(b'\n');
+
+        crate::set_title("Gone fishing");
+        crate::reset_title();
+
+        let cmdline = std::fs::read("/proc/self/cmdline").unwrap();
+        assert_eq!(&cmdline[..args.len()], &args[..]);
+        assert!(cmdline[args.len()..].iter().all(|&b| b == 0));
+        assert_eq!(std::fs::read("/proc/self/comm").unwrap(), comm);
+    }
+
+    #[test]
+    fn set_title_signal_safe_works_in_handler() {
+        extern "C" fn handler(_: libc::c_int) {
+            let title = CStr::from_bytes_with_nul(b"Interrupted by a signal\0").unwrap();
+            let _ = set_title_signal_safe(title);
+        }
+
+        let _lock = crate::test_lock();
+        crate::set_title("Waiting for a signal");
+        unsafe {
+            let previous = libc::signal(
+                libc::SIGUSR1,
+                handler as extern "C" fn(libc::c_int) as libc::sighandler_t,
+            );
+            libc::raise(libc::SIGUSR1);
+            libc::signal(libc::SIGUSR1, previous);
+        }
+
+        assert_eq!(get_title().unwrap(), "Interrupted by a signal");
+        assert_eq!(
+            std::fs::read("/proc/self/comm").unwrap(),
+            b"Interrupted by \n"
+        );
+    }
+
+    #[test]
+    fn set_title_with_mode_sets_only_what_was_asked() {
+        let _lock = crate::test_lock();
+        let cmdline = || {
+            let cmdline = std::fs::read("/proc/self/cmdline").unwrap();
+            let end = cmdline.iter().rposition(|&b| b != 0).unwrap();
+            cmdline[..=end].to_vec()
+        };
+        let comm = || std::fs::read("/proc/self/comm").unwrap();
+
+        crate::set_title("starting up");
+        crate::set_title_with_mode(LinuxTitleMode::Comm, "comm only");
+        assert_eq!(comm(), b"comm only\n");
+        assert_eq!(cmdline(), b"starting up");
+
+        crate::set_title_with_mode(LinuxTitleMode::Argv, "argv only");
+        assert_eq!(comm(), b"comm only\n");
+        assert_eq!(cmdline(), b"argv only");
+
+        crate::set_default_linux_mode(LinuxTitleMode::Comm);
+        crate::set_title("default comm");
+        crate::set_default_linux_mode(LinuxTitleMode::Both);
+        assert_eq!(comm(), b"default comm\n");
+        assert_eq!(cmdline(), b"argv only");
+
+        crate::set_title("both again");
+        assert_eq!(comm(), b"both again\n");
+        assert_eq!(cmdline(), b"both again");
+    }
+
+    #[cfg(feature = "early-init")]
+    #[test]
+    fn early_init_runs_before_main() {
+        assert!(EARLY_INIT_RAN.load(Ordering::Relaxed));
+        assert!(CMDLINE.lock().expect("cmdline lock").is_some());
+    }
+
+    #[test]
+    fn writable_checks_memory_map() {
+        let stack = [0u8; 64];
+        let start = stack.as_ptr() as usize;
+        assert!(writable(start, start + stack.len()));
+
+        let text = writable_checks_memory_map as *const () as usize;
+        assert!(!writable(text, text + 1));
+
+        let stat = Stat::read().unwrap();
+        assert!(writable(stat.arg_start, stat.arg_end));
+    }
+
+    #[test]
+    fn get_title_follows_mode() {
+        let _lock = crate::test_lock();
+        crate::set_title("a title longer than fifteen bytes");
+        assert_eq!(get_title().unwrap(), "a title longer than fifteen bytes");
+
+        crate::set_title_with_mode(LinuxTitleMode::Comm, "comm only");
+        assert_eq!(get_title().unwrap(), "comm only");
+
+        crate::set_title_with_mode(LinuxTitleMode::Argv, "argv only, and long");
+        assert_eq!(get_title().unwrap(), "argv only, and long");
+
+        crate::set_title_with_mode(LinuxTitleMode::Comm, "comm again");
+        crate::reset_title();
+        let args: Vec<_> = std::env::args_os().collect();
+        assert_eq!(get_title().unwrap(), args.join(OsStr::new(" ")));
+    }
+
+    #[test]
+    fn set_title_sets_cmdline() {
+        let _lock = crate::test_lock();
+        let args: Vec<_> = std::env::args_os().collect();
+        crate::set_title("abcdefghijklmnopqrstu");
+
+        let cmdline = std::fs::read("/proc/self/cmdline").unwrap();
+        let end = cmdline.iter().rposition(|&b| b != 0).unwrap();
+        assert_eq!(&cmdline[..=end], b"abcdefghijklmnopqrstu");
+        assert_eq!(get_title().unwrap(), "abcdefghijklmnopqrstu");
+        assert_eq!(
+            std::env::args_os().collect::<Vec<_>>(),
+            args,
+            "argv clobbered"
+        );
+    }
+
+    #[test]
+    fn set_title_agrees_in_comm_and_cmdline() {
+        let _lock = crate::test_lock();
+        let title = "postgres: checkpointer process";
+        crate::set_title(title);
+
+        let comm = std::fs::read("/proc/self/comm").unwrap();
+        let cmdline = std::fs::read("/proc/self/cmdline").unwrap();
+        let end = cmdline.iter().rposition(|&b| b != 0).unwrap();
+        assert_eq!(&cmdline[..=end], title.as_bytes());
+        assert_eq!(comm, b"postgres: check\n");
+        assert!(cmdline.starts_with(&comm[..comm.len() - 1]));
+    }
+
+    #[test]
+    fn max_title_len_matches_cmdline() {
+        let _lock = crate::test_lock();
+        let max = crate::max_title_len().unwrap();
+        crate::set_title("y".repeat(max + 10));
+
+        assert_eq!(get_title().unwrap().len(), max);
+        assert_eq!(crate::max_title_len().unwrap(), max);
+    }
+
+    #[test]
+    fn set_title_expands_into_environ() {
+        let _lock = crate::test_lock();
+        let vars: Vec<_> = std::env::vars_os().collect();
+        let stat = Stat::read().unwrap();
+        let len = stat.arg_end - stat.arg_start + (stat.env_end - stat.env_start) / 2;
+        let title = "z".repeat(len);
+        crate::set_title(&title);
+
+        let cmdline = std::fs::read("/proc/self/cmdline").unwrap();
+        let end = cmdline.iter().rposition(|&b| b != 0).unwrap();
+        assert_eq!(&cmdline[..=end], title.as_bytes());
+        assert_eq!(get_title().unwrap(), &title[..]);
+        assert_eq!(
+            std::env::vars_os().collect::<Vec<_>>(),
+            vars,
+            "environment clobbered"
+        );
+    }
+}
+
+#[cfg(all(
+    feature = "std",
+    not(any(feature = "disabled", feature = "mock")),
+    target_os = "macos"
+))]
+mod imp {
+    use std::ffi::{CStr, CString, OsStr, OsString};
+    use std::os::unix::ffi::{OsStrExt, OsStringExt};
+    use std::sync::Mutex;
+
+    use lazy_static::lazy_static;
+
+    use crate::argv::ArgvRegion;
+    use crate::TitleError;
+
+    pub const SUPPORTED: bool = true;
+
+    pub fn mechanism() -> crate::Mechanism {
+        match ARGV_REGION.lock().expect("argv region lock").is_some() {
+            true => crate::Mechanism::ArgvOverwrite,
+            false => crate::Mechanism::Noop,
+        }
+    }
+
+    lazy_static! {
+        static ref ARGV_REGION: Mutex<Option<ArgvRegion>> = Mutex::new(unsafe {
+            ArgvRegion::take(*libc::_NSGetArgc() as usize, *libc::_NSGetArgv())
+        });
+    }
+
+    pub fn max_title_len() -> Option<usize> {
+        ARGV_REGION
+            .lock()
+            .expect("argv region lock")
+            .as_ref()
+            .map(|region| region.len() - 1)
+    }
+
+    pub fn set_title(title: &OsStr) -> Result<(), TitleError> {
+        // macOS has no setproctitle(), so do what PostgreSQL does and overwrite
+        // the original argv strings in place.  The title is clamped to the
+        // space they occupied so we never stray into adjacent memory.
+        let title = title.as_bytes();
+
+        if title.contains(&0) {
+            return Err(TitleError::InteriorNul);
+        }
+
+        match ARGV_REGION.lock().expect("argv region lock").as_mut() {
+            Some(region) => crate::check_truncation(region.write(title), title.len()),
+            None => Err(TitleError::Unsupported),
+        }
+    }
+
+    pub fn set_thread_title(title: &OsStr) -> Result<(), TitleError> {
+        let title = title.as_bytes();
+
+        if title.contains(&0) {
+            return Err(TitleError::InteriorNul);
+        }
+
+        // Names longer than MAXTHREADNAMESIZE are rejected outright.
+        let title = CString::new(&title[..title.len().min(63)])?;
+        match unsafe { libc::pthread_setname_np(title.as_ptr()) } {
+            0 => Ok(()),
+            e => Err(std::io::Error::from_raw_os_error(e).into()),
+        }
+    }
+
+    pub fn get_thread_title() -> Option<OsString> {
+        None
+    }
+
+    pub fn set_title_signal_safe(title: &CStr) -> Result<(), TitleError> {
+        match unsafe { crate::argv::write_signal_safe(title.to_bytes()) } {
+            true => Ok(()),
+            false => Err(TitleError::Unsupported),
+        }
+    }
+
+    pub fn reset_title() -> Result<(), TitleError> {
+        match ARGV_REGION.lock().expect("argv region lock").as_mut() {
+            Some(region) => {
+                region.restore();
+                Ok(())
+            }
+            None => Err(TitleError::Unsupported),
+        }
+    }
+
+    pub fn get_title() -> Option<OsString> {
+        ARGV_REGION
+            .lock()
+            .expect("argv region lock")
+            .as_ref()
+            .map(|region| OsString::from_vec(region.read()))
+    }
+
+    #[test]
+    fn set_title_sets_procargs() {
+        let _lock = crate::test_lock();
+        fn procargs_argv0() -> Vec<u8> {
+            let pid = unsafe { libc::getpid() };
+            let mut mib = [libc::CTL_KERN, libc::KERN_PROCARGS2, pid];
+            let mut buf = vec![0u8; 65536];
+            let mut len = buf.len();
+            let ret = unsafe {
+                libc::sysctl(
+                    mib.as_mut_ptr(),
+                    mib.len() as u32,
+                    buf.as_mut_ptr() as *mut libc::c_void,
+                    &mut len,
+                    std::ptr::null_mut(),
+                    0,
+                )
+            };
+            assert_eq!(ret, 0, "sysctl(KERN_PROCARGS2) failed");
+
+            // Skip argc, the executable path, and the NUL padding after it.
+            let buf = &buf[std::mem::size_of::<libc::c_int>()..len];
+            let path_end = buf.iter().position(|&b| b == 0).unwrap();
+            let args = &buf[path_end..];
+            let args = &args[args.iter().position(|&b| b != 0).unwrap()..];
+            args.split(|&b| b == 0).next().unwrap().to_vec()
+        }
+
+        let args: Vec<_> = std::env::args_os().collect();
+        crate::set_title("Grumpy old men");
+
+        assert_eq!(procargs_argv0(), b"Grumpy old men");
+        assert_eq!(get_title().unwrap(), "Grumpy old men");
+        assert_eq!(
+            std::env::args_os().collect::<Vec<_>>(),
+            args,
+            "argv clobbered"
+        );
+    }
+}
+
+#[cfg(all(
+    feature = "std",
+    not(any(feature = "disabled", feature = "mock")),
+    any(target_os = "solaris", target_os = "illumos")
+))]
+mod imp {
+    use std::ffi::{CStr, CString, OsStr, OsString};
+    use std::os::raw::{c_char, c_int};
+    use std::os::unix::ffi::{OsStrExt, OsStringExt};
+    use std::sync::Mutex;
+
+    use lazy_static::lazy_static;
+
+    use crate::argv::ArgvRegion;
+    use crate::TitleError;
+
+    pub const SUPPORTED: bool = true;
+
+    pub fn mechanism() -> crate::Mechanism {
+        match ARGV_REGION.lock().expect("argv region lock").is_some() {
+            true => crate::Mechanism::ArgvOverwrite,
+            false => crate::Mechanism::Noop,
+        }
+    }
+
+    /// The leading fields of `psinfo_t`, as found in `/proc/self/psinfo`.
+    /// See proc(5).
+    #[repr(C)]
+    #[allow(dead_code)]
+    struct PsInfo {
+        pr_flag: c_int,
+        pr_nlwp: c_int,
+        pr_pid: libc::pid_t,
+        pr_ppid: libc::pid_t,
+        pr_pgid: libc::pid_t,
+        pr_sid: libc::pid_t,
+        pr_uid: libc::uid_t,
+        pr_euid: libc::uid_t,
+        pr_gid: libc::gid_t,
+        pr_egid: libc::gid_t,
+        pr_addr: libc::uintptr_t,
+        pr_size: libc::size_t,
+        pr_rssize: libc::size_t,
+        pr_pad1: libc::size_t,
+        pr_ttydev: libc::dev_t,
+        pr_pctcpu: u16,
+        pr_pctmem: u16,
+        pr_start: libc::timespec,
+        pr_time: libc::timespec,
+        pr_ctime: libc::timespec,
+        pr_fname: [c_char; 16],
+        pr_psargs: [c_char; 80],
+        pr_wstat: c_int,
+        pr_argc: c_int,
+        pr_argv: libc::uintptr_t,
+        pr_envp: libc::uintptr_t,
+    }
+
+    impl PsInfo {
+        fn read() -> Option<Self> {
+            let buf = std::fs::read("/proc/self/psinfo").ok()?;
+            if buf.len() < std::mem::size_of::<Self>() {
+                return None;
+            }
+
+            Some(unsafe { std::ptr::read_unaligned(buf.as_ptr() as *const Self) })
+        }
+    }
+
+    lazy_static! {
+        static ref ARGV_REGION: Mutex<Option<ArgvRegion>> =
+            Mutex::new(PsInfo::read().and_then(|info| unsafe {
+                ArgvRegion::take(info.pr_argc as usize, info.pr_argv as *mut *mut c_char)
+            }));
+    }
+
+    pub fn max_title_len() -> Option<usize> {
+        ARGV_REGION
+            .lock()
+            .expect("argv region lock")
+            .as_ref()
+            .map(|region| region.len() - 1)
+    }
+
+    pub fn set_title(title: &OsStr) -> Result<(), TitleError> {
+        // As on macOS, there's no setproctitle(), so overwrite the original
+        // argv strings where pargs(1) and friends will find them.  ps(1) only
+        // shows the copy the kernel took at exec time, so won't notice.
+        let title = title.as_bytes();
+
+        if title.contains(&0) {
+            return Err(TitleError::InteriorNul);
+        }
+
+        match ARGV_REGION.lock().expect("argv region lock").as_mut() {
+            Some(region) => crate::check_truncation(region.write(title), title.len()),
+            None => Err(TitleError::Unsupported),
+        }
+    }
+
+    pub fn set_thread_title(title: &OsStr) -> Result<(), TitleError> {
+        let title = title.as_bytes();
+
+        if title.contains(&0) {
+            return Err(TitleError::InteriorNul);
+        }
+
+        // Names longer than PTHREAD_MAX_NAMELEN_NP are rejected with ERANGE.
+        let title = CString::new(&title[..title.len().min(31)])?;
+        match unsafe { libc::pthread_setname_np(libc::pthread_self(), title.as_ptr()) } {
+            0 => Ok(()),
+            e => Err(std::io::Error::from_raw_os_error(e).into()),
+        }
+    }
+
+    pub fn get_thread_title() -> Option<OsString> {
+        None
+    }
+
+    pub fn set_title_signal_safe(title: &CStr) -> Result<(), TitleError> {
+        match unsafe { crate::argv::write_signal_safe(title.to_bytes()) } {
+            true => Ok(()),
+            false => Err(TitleError::Unsupported),
+        }
+    }
+
+    pub fn reset_title() -> Result<(), TitleError> {
+        match ARGV_REGION.lock().expect("argv region lock").as_mut() {
+            Some(region) => {
+                region.restore();
+                Ok(())
+            }
+            None => Err(TitleError::Unsupported),
+        }
+    }
+
+    pub fn get_title() -> Option<OsString> {
+        ARGV_REGION
+            .lock()
+            .expect("argv region lock")
+            .as_ref()
+            .map(|region| OsString::from_vec(region.read()))
+    }
+
+    #[test]
+    fn set_title_sets_pargs() {
+        let _lock = crate::test_lock();
+        let args: Vec<_> = std::env::args_os().collect();
+        crate::set_title("Slartibartfast");
+
+        assert_eq!(get_title().unwrap(), "Slartibartfast");
+        assert_eq!(
+            std::env::args_os().collect::<Vec<_>>(),
+            args,
+            "argv clobbered"
+        );
+
+        // pargs(1) reads argv out of our address space, so should agree.
+        let pid = std::process::id().to_string();
+        if let Ok(out) = std::process::Command::new("pargs").arg(&pid).output() {
+            let out = String::from_utf8_lossy(&out.stdout);
+            assert!(out.contains("argv[0]: Slartibartfast"), "pargs: {}", out);
+        }
+    }
+
+    #[test]
+    fn set_title_truncates_to_argv() {
+        let _lock = crate::test_lock();
+        let max = max_title_len().unwrap();
+        crate::set_title("x".repeat(max + 100));
+        assert_eq!(get_title().unwrap().len(), max);
+    }
+}
+
+#[cfg(all(
+    feature = "std",
+    not(any(feature = "disabled", feature = "mock")),
+    target_os = "haiku"
+))]
+mod imp {
+    use std::ffi::{CStr, CString, OsStr, OsString};
+    use std::os::unix::ffi::{OsStrExt, OsStringExt};
+
+    use crate::TitleError;
+
+    pub const SUPPORTED: bool = true;
+
+    pub fn mechanism() -> crate::Mechanism {
+        crate::Mechanism::RenameThread
+    }
+
+    pub fn max_title_len() -> Option<usize> {
+        Some(libc::B_OS_NAME_LENGTH - 1)
+    }
+
+    /// Rename a thread, truncating to fit B_OS_NAME_LENGTH.
+    fn rename(thread: libc::thread_id, title: &[u8]) -> Result<(), TitleError> {
+        if title.contains(&0) {
+            return Err(TitleError::InteriorNul);
+        }
+
+        let len = title.len().min(libc::B_OS_NAME_LENGTH - 1);
+        let name = CString::new(&title[..len])?;
+        match unsafe { libc::rename_thread(thread, name.as_ptr()) } {
+            libc::B_OK => crate::check_truncation(len, title.len()),
+            e => Err(std::io::Error::from_raw_os_error(e).into()),
+        }
+    }
+
+    pub fn set_title(title: &OsStr) -> Result<(), TitleError> {
+        // Haiku has no way to rename a team, so name its main thread, which
+        // shares the team's ID.
+        rename(unsafe { libc::getpid() }, title.as_bytes())
+    }
+
+    pub fn set_thread_title(title: &OsStr) -> Result<(), TitleError> {
+        rename(
+            unsafe { libc::find_thread(std::ptr::null()) },
+            title.as_bytes(),
+        )
+    }
+
+    pub fn get_thread_title() -> Option<OsString> {
+        None
+    }
+
+    pub fn reset_title() -> Result<(), TitleError> {
+        Err(TitleError::Unsupported)
+    }
+
+    pub fn set_title_signal_safe(_title: &CStr) -> Result<(), TitleError> {
+        Err(TitleError::Unsupported)
+    }
+
+    pub fn get_title() -> Option<OsString> {
+        let mut info = std::mem::MaybeUninit::<libc::thread_info>::uninit();
+        if unsafe { libc::get_thread_info(libc::getpid(), info.as_mut_ptr()) } != libc::B_OK {
+            return None;
+        }
+
+        let info = unsafe { info.assume_init() };
+        let name = unsafe { CStr::from_ptr(info.name.as_ptr()) };
+        Some(OsString::from_vec(name.to_bytes().to_vec()))
+    }
+
+    #[test]
+    fn set_title_renames_main_thread() {
+        let _lock = crate::test_lock();
+        crate::set_title("Be Inc. would be proud of this");
+        assert_eq!(get_title().unwrap(), "Be Inc. would be proud of this");
+
+        crate::set_title("x".repeat(100));
+        assert_eq!(get_title().unwrap().len(), libc::B_OS_NAME_LENGTH - 1);
+    }
+}
+
+#[cfg(all(
+    feature = "std",
+    not(any(feature = "disabled", feature = "mock")),
+    target_os = "nto"
+))]
+mod imp {
+    use std::ffi::{CStr, CString, OsStr, OsString};
+    use std::os::raw::c_char;
+    use std::os::unix::ffi::{OsStrExt, OsStringExt};
+
+    use lazy_static::lazy_static;
+
+    use crate::TitleError;
+
+    pub const SUPPORTED: bool = true;
+
+    /// The main thread's ID, which is always 1 under QNX.
+    const MAIN_THREAD: libc::pthread_t = 1;
+
+    /// The longest thread name, NUL included.
+    const NAME_MAX: usize = libc::_NTO_THREAD_NAME_MAX as usize;
+
+    pub fn mechanism() -> crate::Mechanism {
+        crate::Mechanism::RenameThread
+    }
+
+    pub fn max_title_len() -> Option<usize> {
+        Some(NAME_MAX - 1)
+    }
+
+    lazy_static! {
+        // The main thread's name before we changed it, for reset_title().
+        static ref ORIGINAL_NAME: Option<Vec<u8>> = thread_name(MAIN_THREAD);
+    }
+
+    fn thread_name(thread: libc::pthread_t) -> Option<Vec<u8>> {
+        let mut buf = [0 as c_char; NAME_MAX];
+        if unsafe { libc::pthread_getname_np(thread, buf.as_mut_ptr(), buf.len() as _) } != 0 {
+            return None;
+        }
+
+        Some(unsafe { CStr::from_ptr(buf.as_ptr()) }.to_bytes().to_vec())
+    }
+
+    /// Rename a thread, truncating to fit _NTO_THREAD_NAME_MAX.
+    fn rename(thread: libc::pthread_t, title: &[u8]) -> Result<(), TitleError> {
+        if title.contains(&0) {
+            return Err(TitleError::InteriorNul);
+        }
+
+        let len = title.len().min(NAME_MAX - 1);
+        let name = CString::new(&title[..len])?;
+        match unsafe { libc::pthread_setname_np(thread, name.as_ptr()) } {
+            0 => crate::check_truncation(len, title.len()),
+            e => Err(std::io::Error::from_raw_os_error(e).into()),
+        }
+    }
+
+    pub fn set_title(title: &OsStr) -> Result<(), TitleError> {
+        // QNX names processes after the path they were run from, with no way
+        // to change it, so name the main thread, as shown by `pidin` and the
+        // IDE's System Information view.
+        lazy_static::initialize(&ORIGINAL_NAME);
+        rename(MAIN_THREAD, title.as_bytes())
+    }
+
+    pub fn set_thread_title(title: &OsStr) -> Result<(), TitleError> {
+        lazy_static::initialize(&ORIGINAL_NAME);
+        rename(unsafe { libc::pthread_self() }, title.as_bytes())
+    }
+
+    pub fn get_thread_title() -> Option<OsString> {
+        None
+    }
+
+    pub fn reset_title() -> Result<(), TitleError> {
+        match ORIGINAL_NAME.as_ref() {
+            Some(name) => rename(MAIN_THREAD, name),
+            None => Ok(()),
+        }
+    }
+
+    pub fn set_title_signal_safe(_title: &CStr) -> Result<(), TitleError> {
+        Err(TitleError::Unsupported)
+    }
+
+    pub fn get_title() -> Option<OsString> {
+        thread_name(MAIN_THREAD).map(OsString::from_vec)
+    }
+
+    #[test]
+    fn set_title_renames_main_thread() {
+        let _lock = crate::test_lock();
+        crate::set_title("Everything is a message");
+        assert_eq!(get_title().unwrap(), "Everything is a message");
+
+        crate::set_title("x".repeat(200));
+        assert_eq!(get_title().unwrap().len(), NAME_MAX - 1);
+
+        crate::reset_title();
+        assert_eq!(get_title().map(OsString::into_vec), *ORIGINAL_NAME);
+    }
+}
+
+#[cfg(all(
+    feature = "std",
+    not(any(feature = "disabled", feature = "mock")),
+    target_os = "hurd"
+))]
+mod imp {
+    use std::ffi::{CStr, OsStr, OsString};
+    use std::os::raw::{c_char, c_int};
+    use std::os::unix::ffi::{OsStrExt, OsStringExt};
+    use std::sync::atomic::{AtomicPtr, AtomicUsize, Ordering};
+    use std::sync::Mutex;
+
+    use lazy_static::lazy_static;
+
+    use crate::argv::ArgvRegion;
+    use crate::TitleError;
+
+    pub const SUPPORTED: bool = true;
+
+    pub fn mechanism() -> crate::Mechanism {
+        match ARGV_REGION.lock().expect("argv region lock").is_some() {
+            true => crate::Mechanism::ArgvOverwrite,
+            false => crate::Mechanism::Noop,
+        }
+    }
+
+    static ARGC: AtomicUsize = AtomicUsize::new(0);
+    static ARGV: AtomicPtr<*mut c_char> = AtomicPtr::new(std::ptr::null_mut());
+
+    // There's no procfs or sysctl telling us where argv lives, but glibc
+    // passes functions in .init_array the same arguments as main().
+    extern "C" fn capture_args(argc: c_int, argv: *mut *mut c_char, _envp: *mut *mut c_char) {
+        ARGC.store(argc as usize, Ordering::SeqCst);
+        ARGV.store(argv, Ordering::SeqCst);
+    }
+
+    #[used]
+    #[link_section = ".init_array"]
+    static CAPTURE_ARGS: extern "C" fn(c_int, *mut *mut c_char, *mut *mut c_char) = capture_args;
+
+    lazy_static! {
+        static ref ARGV_REGION: Mutex<Option<ArgvRegion>> = Mutex::new(unsafe {
+            ArgvRegion::take(ARGC.load(Ordering::SeqCst), ARGV.load(Ordering::SeqCst))
+        });
+    }
+
+    pub fn max_title_len() -> Option<usize> {
+        ARGV_REGION
+            .lock()
+            .expect("argv region lock")
+            .as_ref()
+            .map(|region| region.len() - 1)
+    }
+
+    pub fn set_title(title: &OsStr) -> Result<(), TitleError> {
+        // The proc server reads arguments from where glibc told it they were
+        // at startup, so overwriting the originals in place is enough for ps.
+        let title = title.as_bytes();
+
+        if title.contains(&0) {
+            return Err(TitleError::InteriorNul);
+        }
+
+        match ARGV_REGION.lock().expect("argv region lock").as_mut() {
+            Some(region) => crate::check_truncation(region.write(title), title.len()),
+            None => Err(TitleError::Unsupported),
+        }
+    }
+
+    pub fn set_thread_title(_title: &OsStr) -> Result<(), TitleError> {
+        Err(TitleError::Unsupported)
+    }
+
+    pub fn get_thread_title() -> Option<OsString> {
+        None
+    }
+
+    pub fn set_title_signal_safe(title: &CStr) -> Result<(), TitleError> {
+        match unsafe { crate::argv::write_signal_safe(title.to_bytes()) } {
+            true => Ok(()),
+            false => Err(TitleError::Unsupported),
+        }
+    }
+
+    pub fn reset_title() -> Result<(), TitleError> {
+        match ARGV_REGION.lock().expect("argv region lock").as_mut() {
+            Some(region) => {
+                region.restore();
+                Ok(())
+            }
+            None => Err(TitleError::Unsupported),
+        }
+    }
+
+    pub fn get_title() -> Option<OsString> {
+        ARGV_REGION
+            .lock()
+            .expect("argv region lock")
+            .as_ref()
+            .map(|region| OsString::from_vec(region.read()))
+    }
+
+    #[test]
+    fn set_title_overwrites_argv() {
+        let _lock = crate::test_lock();
+        let args: Vec<_> = std::env::args_os().collect();
+        crate::set_title("Hurd it through the grapevine");
+
+        assert_eq!(get_title().unwrap(), "Hurd it through the grapevine");
+        assert_eq!(
+            std::env::args_os().collect::<Vec<_>>(),
+            args,
+            "argv clobbered"
+        );
+    }
+}
+
+#[cfg(all(
+    feature = "std",
+    not(any(feature = "disabled", feature = "mock")),
+    target_os = "windows"
+))]
+mod imp {
+    use std::cell::RefCell;
+    use std::ffi::{CStr, OsStr, OsString};
+    use std::io::Write;
+    use std::os::windows::ffi::{OsStrExt, OsStringExt};
+    use std::sync::atomic::{AtomicBool, Ordering};
+
+    use lazy_static::lazy_static;
+    use winapi::shared::minwindef::FARPROC;
+    use winapi::shared::ntdef::{HRESULT, PCWSTR};
+    use winapi::shared::winerror::{
+        ERROR_INSUFFICIENT_BUFFER, FACILITY_WIN32, HRESULT_CODE, HRESULT_FACILITY,
+    };
+    use winapi::um::consoleapi::GetConsoleMode;
+    use winapi::um::errhandlingapi::{GetLastError, SetLastError};
+    use winapi::um::libloaderapi::{GetModuleHandleW, GetProcAddress};
+    use winapi::um::processenv::GetStdHandle;
+    use winapi::um::processthreadsapi::GetCurrentThread;
+    use winapi::um::winbase::STD_OUTPUT_HANDLE;
+    use winapi::um::wincon::{
+        GetConsoleTitleW, GetConsoleWindow, SetConsoleTitleW, ENABLE_VIRTUAL_TERMINAL_PROCESSING,
+    };
+    use winapi::um::winnt::HANDLE;
+
+    use crate::{TitleError, MAX_CONSOLE_TITLE};
+
+    pub const SUPPORTED: bool = true;
+
+    pub fn mechanism() -> crate::Mechanism {
+        if cfg!(feature = "windows-event-handle") {
+            crate::Mechanism::ConsoleAndEvent
+        } else {
+            crate::Mechanism::Console
+        }
+    }
+
+    pub fn max_title_len() -> Option<usize> {
+        Some(MAX_CONSOLE_TITLE)
+    }
+
+    pub fn has_console() -> bool {
+        !unsafe { GetConsoleWindow() }.is_null()
+    }
+
+    /// A named event object to expose the title to tools like Process Explorer
+    /// in the absence of a console.
+    #[cfg(feature = "windows-event-handle")]
+    mod event {
+        use std::ffi::{OsStr, OsString};
+        use std::io;
+        use std::os::windows::ffi::{OsStrExt, OsStringExt};
+        use std::os::windows::io::RawHandle;
+        use std::sync::Mutex;
+
+        use lazy_static::lazy_static;
+        use winapi::shared::minwindef::{FARPROC, MAX_PATH};
+        use winapi::shared::ntdef::{
+            BOOLEAN, NTSTATUS, OBJECT_ATTRIBUTES, PVOID, ULONG, UNICODE_STRING,
+        };
+        use winapi::shared::ntstatus::{STATUS_MORE_ENTRIES, STATUS_NO_MORE_ENTRIES};
+        use winapi::shared::winerror::ERROR_ALREADY_EXISTS;
+        use winapi::um::handleapi::CloseHandle;
+        use winapi::um::processthreadsapi::{GetCurrentProcessId, ProcessIdToSessionId};
+        use winapi::um::synchapi::CreateEventW;
+        use winapi::um::winnt::{ACCESS_MASK, HANDLE};
+
+        use super::system_fn;
+
+        struct NamedHandle {
+            handle: HANDLE,
+            name: Vec<u16>,
+            prefix_len: usize,
+            // Whether the event already existed, opened rather than created.
+            shared: bool,
+        }
+        unsafe impl Send for NamedHandle {}
+
+        impl NamedHandle {
+            fn create(name: Vec<u16>, prefix_len: usize) -> io::Result<Self> {
+                assert!(name.ends_with(&[0]));
+
+                let handle = unsafe { CreateEventW(std::ptr::null_mut(), 1, 0, name.as_ptr()) };
+                let err = io::Error::last_os_error();
+                if handle.is_null() {
+                    return Err(err);
+                }
+
+                Ok(Self {
+                    handle,
+                    name,
+                    prefix_len,
+                    shared: err.raw_os_error() == Some(ERROR_ALREADY_EXISTS as i32),
+                })
+            }
+        }
+
+        impl Drop for NamedHandle {
+            fn drop(&mut self) {
+                if !self.handle.is_null() {
+                    unsafe { CloseHandle(self.handle) };
+                }
+            }
+        }
+
+        lazy_static! {
+            static ref EVENT_HANDLE: Mutex<Option<NamedHandle>> = Mutex::new(None);
+            static ref PREFIX: Mutex<Option<OsString>> = Mutex::new(None);
+        }
+
+        pub fn set_prefix(prefix: &OsStr) {
+            let prefix = Some(prefix.to_owned()).filter(|p| !p.is_empty());
+            *PREFIX.lock().expect("event prefix lock") = prefix;
+        }
+
+        /// Replace the event with one named after `title`, which must be
+        /// NUL-terminated.
+        pub fn set_name(title: &[u16]) -> io::Result<()> {
+            let (mut name, prefix_len) = match PREFIX.lock().expect("event prefix lock").as_ref() {
+                Some(prefix) => {
+                    let mut name: Vec<u16> = prefix.encode_wide().collect();
+                    let prefix_len = name.len();
+                    name.extend_from_slice(title);
+                    (name, prefix_len)
+                }
+                None => (title.to_vec(), 0),
+            };
+
+            // Object names are limited to MAX_PATH, far short of a console
+            // title, and CreateEventW() fails outright beyond it, so cut the
+            // name short rather than lose it.
+            if name.len() > MAX_PATH {
+                name.truncate(MAX_PATH - 1);
+                if let Some(0xD800..=0xDBFF) = name.last() {
+                    name.pop();
+                }
+                name.push(0);
+            }
+            let prefix_len = prefix_len.min(name.len() - 1);
+
+            let mut event = EVENT_HANDLE.lock().expect("event handle lock");
+            let shared = match event.as_ref() {
+                Some(handle) if handle.name == name => handle.shared,
+                _ => {
+                    // Close the old handle first, so we never hold two at once.
+                    event.take();
+                    let handle = NamedHandle::create(name, prefix_len)?;
+                    let shared = handle.shared;
+                    *event = Some(handle);
+                    shared
+                }
+            };
+
+            // Someone else has an event by this name, most likely another
+            // process with the same title.  It still shows up for us, but
+            // they'll see each other's handle.
+            if shared {
+                return Err(io::Error::from_raw_os_error(ERROR_ALREADY_EXISTS as i32));
+            }
+            Ok(())
+        }
+
+        /// Close the event, if any.
+        pub fn clear() {
+            EVENT_HANDLE.lock().expect("event handle lock").take();
+        }
+
+        /// The title the event was last named after, without any prefix.
+        pub fn title() -> Option<OsString> {
+            EVENT_HANDLE
+                .lock()
+                .expect("event handle lock")
+                .as_ref()
+                .map(|handle| {
+                    OsString::from_wide(&handle.name[handle.prefix_len..handle.name.len() - 1])
+                })
+        }
+
+        /// The full name of the event, prefix and all.
+        pub fn name() -> Option<OsString> {
+            EVENT_HANDLE
+                .lock()
+                .expect("event handle lock")
+                .as_ref()
+                .map(|handle| OsString::from_wide(&handle.name[..handle.name.len() - 1]))
+        }
+
+        pub fn raw_handle() -> Option<RawHandle> {
+            EVENT_HANDLE
+                .lock()
+                .expect("event handle lock")
+                .as_ref()
+                .map(|handle| handle.handle as RawHandle)
+        }
+
+        // The Object Manager's directory API is only exported by ntdll, and
+        // isn't in winapi, so it's looked up at runtime.
+        type NtOpenDirectoryObject =
+            unsafe extern "system" fn(*mut HANDLE, ACCESS_MASK, *mut OBJECT_ATTRIBUTES) -> NTSTATUS;
+        type NtQueryDirectoryObject = unsafe extern "system" fn(
+            HANDLE,
+            PVOID,
+            ULONG,
+            BOOLEAN,
+            BOOLEAN,
+            *mut ULONG,
+            *mut ULONG,
+        ) -> NTSTATUS;
+        type RtlNtStatusToDosError = unsafe extern "system" fn(NTSTATUS) -> ULONG;
+
+        const DIRECTORY_QUERY: ACCESS_MASK = 0x0001;
+
+        #[repr(C)]
+        struct ObjectDirectoryInformation {
+            name: UNICODE_STRING,
+            type_name: UNICODE_STRING,
+        }
+
+        fn wide(s: &str) -> Vec<u16> {
+            OsStr::new(s).encode_wide().collect()
+        }
+
+        /// The titles of events in our namespace named starting with `prefix`,
+        /// which may begin with `Global\` or `Local\`, as set_prefix().
+        pub fn find(prefix: &OsStr) -> io::Result<Vec<OsString>> {
+            let unsupported = || io::Error::from(io::ErrorKind::Unsupported);
+            let (open, query, to_dos) = unsafe {
+                match (
+                    system_fn("ntdll.dll", b"NtOpenDirectoryObject\0"),
+                    system_fn("ntdll.dll", b"NtQueryDirectoryObject\0"),
+                    system_fn("ntdll.dll", b"RtlNtStatusToDosError\0"),
+                ) {
+                    (Some(open), Some(query), Some(to_dos)) => (
+                        std::mem::transmute::<FARPROC, NtOpenDirectoryObject>(open),
+                        std::mem::transmute::<FARPROC, NtQueryDirectoryObject>(query),
+                        std::mem::transmute::<FARPROC, RtlNtStatusToDosError>(to_dos),
+                    ),
+                    _ => return Err(unsupported()),
+                }
+            };
+            let error = |status| io::Error::from_raw_os_error(unsafe { to_dos(status) } as i32);
+
+            // Unqualified names live in the session's own directory, which
+            // for session 0 is the global one.
+            let prefix: Vec<u16> = prefix.encode_wide().collect();
+            let (mut dir, prefix) = match prefix.strip_prefix(&wide("Global\\")[..]) {
+                Some(rest) => (wide("\\BaseNamedObjects"), rest),
+                None => {
+                    let mut session = 0;
+                    unsafe { ProcessIdToSessionId(GetCurrentProcessId(), &mut session) };
+                    let dir = match session {
+                        0 => wide("\\BaseNamedObjects"),
+                        n => wide(&format!("\\Sessions\\{}\\BaseNamedObjects", n)),
+                    };
+                    let local = wide("Local\\");
+                    (dir, prefix.strip_prefix(&local[..]).unwrap_or(&prefix))
+                }
+            };
+
+            let mut name = UNICODE_STRING {
+                Length: (dir.len() * 2) as u16,
+                MaximumLength: (dir.len() * 2) as u16,
+                Buffer: dir.as_mut_ptr(),
+            };
+            let mut attrs = OBJECT_ATTRIBUTES {
+                Length: std::mem::size_of::<OBJECT_ATTRIBUTES>() as ULONG,
+                RootDirectory: std::ptr::null_mut(),
+                ObjectName: &mut name,
+                Attributes: 0,
+                SecurityDescriptor: std::ptr::null_mut(),
+                SecurityQualityOfService: std::ptr::null_mut(),
+            };
+            let mut handle = std::ptr::null_mut();
+            let status = unsafe { open(&mut handle, DIRECTORY_QUERY, &mut attrs) };
+            if status < 0 {
+                return Err(error(status));
+            }
+
+            let event = wide("Event");
+            let mut titles = Vec::new();
+            let mut buf = vec![0u64; 8 * 1024];
+            let (mut context, mut restart) = (0, 1);
+            let status = loop {
+                let mut returned = 0;
+                let status = unsafe {
+                    query(
+                        handle,
+                        buf.as_mut_ptr() as PVOID,
+                        (buf.len() * 8) as ULONG,
+                        0,
+                        restart,
+                        &mut context,
+                        &mut returned,
+                    )
+                };
+                restart = 0;
+                if status < 0 {
+                    break status;
+                }
+
+                // Entries run until an empty one, with their names after.
+                let entries = buf.as_ptr() as *const ObjectDirectoryInformation;
+                let max = returned as usize / std::mem::size_of::<ObjectDirectoryInformation>();
+                for i in 0..max {
+                    let entry = unsafe { &*entries.add(i) };
+                    if entry.name.Buffer.is_null() {
+                        break;
+                    }
+
+                    let string = |s: &UNICODE_STRING| unsafe {
+                        std::slice::from_raw_parts(s.Buffer, s.Length as usize / 2)
+                    };
+                    let name = string(&entry.name);
+                    if string(&entry.type_name) == &event[..] && name.starts_with(prefix) {
+                        titles.push(OsString::from_wide(&name[prefix.len()..]));
+                    }
+                }
+
+                if status != STATUS_MORE_ENTRIES {
+                    break status;
+                }
+            };
+            unsafe { CloseHandle(handle) };
+
+            match status {
+                STATUS_NO_MORE_ENTRIES => Ok(titles),
+                status if status < 0 => Err(error(status)),
+                _ => Ok(titles),
+            }
+        }
+
+        #[test]
+        fn find_lists_titles_by_prefix() {
+            let _lock = crate::test_lock();
+            crate::set_title_prefix("proctitle-find:");
+            crate::set_title("worker 1");
+            crate::set_title_prefix("");
+
+            let titles = crate::find_titles("proctitle-find:").unwrap();
+            assert!(titles.iter().any(|t| t == "worker 1"), "{:?}", titles);
+            assert!(!crate::find_titles("proctitle-nobody:")
+                .unwrap()
+                .iter()
+                .any(|t| t == "worker 1"));
+            crate::reset_title();
+        }
+
+        #[test]
+        fn set_name_keeps_handle_for_unchanged_title() {
+            let _lock = crate::test_lock();
+            let handle = || {
+                EVENT_HANDLE
+                    .lock()
+                    .expect("event handle lock")
+                    .as_ref()
+                    .map(|handle| handle.handle as usize)
+            };
+
+            crate::set_title("steady");
+            let first = handle().unwrap();
+            crate::set_title("steady");
+            assert_eq!(handle().unwrap(), first);
+        }
+
+        #[test]
+        fn set_name_clamps_to_max_path() {
+            let _lock = crate::test_lock();
+            let fits = "x".repeat(MAX_PATH - 1);
+            assert!(crate::set_title_checked(&fits).is_ok());
+            assert_eq!(title().unwrap(), OsString::from(&fits));
+
+            // Too long for the event, but not the console.
+            let title_len = MAX_PATH + 40;
+            assert!(crate::set_title_checked("y".repeat(title_len)).is_ok());
+            assert_eq!(title().unwrap().len(), MAX_PATH - 1);
+
+            // Too long for both, so truncated rather than rejected.
+            assert!(matches!(
+                crate::set_title_checked("\u{1F600}".repeat(2500)),
+                Err(crate::TitleError::Truncated { .. })
+            ));
+            let name: Vec<u16> = name().unwrap().encode_wide().collect();
+            assert_eq!(name.len(), MAX_PATH - 2, "split a surrogate pair");
+
+            crate::reset_title();
+        }
+
+        #[test]
+        fn cleanup_closes_event() {
+            let _lock = crate::test_lock();
+            crate::set_title("short-lived");
+            assert!(raw_handle().is_some());
+
+            crate::cleanup();
+            assert!(raw_handle().is_none());
+            assert!(name().is_none());
+        }
+
+        #[test]
+        fn set_name_reports_shared_event() {
+            let _lock = crate::test_lock();
+            let name: Vec<u16> = OsStr::new("proctitle shared event\0")
+                .encode_wide()
+                .collect();
+            let other = unsafe { CreateEventW(std::ptr::null_mut(), 1, 0, name.as_ptr()) };
+            assert!(!other.is_null());
+
+            // Setting the same title again keeps the handle, still shared.
+            for _ in 0..2 {
+                match crate::set_title_checked("proctitle shared event") {
+                    Err(crate::TitleError::Os(e)) => {
+                        assert_eq!(e.raw_os_error(), Some(ERROR_ALREADY_EXISTS as i32))
+                    }
+                    other => panic!("unexpected result: {:?}", other),
+                }
+            }
+            assert_eq!(title().unwrap(), "proctitle shared event");
+
+            crate::reset_title();
+            unsafe { CloseHandle(other) };
+        }
+
+        #[test]
+        fn set_prefix_prefixes_event_name() {
+            let _lock = crate::test_lock();
+            set_prefix(OsStr::new("Local\\myapp:"));
+            crate::set_title("worker");
+            set_prefix(OsStr::new(""));
+
+            assert_eq!(name().unwrap(), "Local\\myapp:worker");
+            assert_eq!(title().unwrap(), "worker");
+        }
+    }
+
+    thread_local! {
+        // Scratch space for encoding titles, so frequent updates don't keep
+        // going back to the allocator.
+        static TITLE_BUF: RefCell<Vec<u16>> = const { RefCell::new(Vec::new()) };
+    }
+
+    /// Encode a title into `buf` as NUL-terminated UTF-16, truncated to
+    /// `MAX_CONSOLE_TITLE` code units without splitting a surrogate pair,
+    /// returning its full length.
+    ///
+    /// Interior NULs are rejected, as on other platforms, rather than letting
+    /// them silently cut the title short.
+    fn encode_title(title: &OsStr, buf: &mut Vec<u16>) -> Result<usize, TitleError> {
+        let mut len = 0;
+        for c in title.encode_wide() {
+            if c == 0 {
+                return Err(TitleError::InteriorNul);
+            }
+            len += 1;
+        }
+
+        buf.clear();
+        buf.extend(title.encode_wide().take(MAX_CONSOLE_TITLE + 1));
+
+        if buf.len() > MAX_CONSOLE_TITLE {
+            buf.truncate(MAX_CONSOLE_TITLE);
+            if let Some(0xD800..=0xDBFF) = buf.last() {
+                buf.pop();
+            }
+        }
+
+        buf.push(0);
+        Ok(len)
+    }
+
+    /// Run `f` with `title` encoded in this thread's scratch buffer, letting
+    /// the caller know if it had to be truncated.
+    fn with_encoded_title<F>(title: &OsStr, f: F) -> Result<(), TitleError>
+    where
+        F: FnOnce(&[u16]) -> Result<(), TitleError>,
+    {
+        TITLE_BUF.with(|buf| {
+            let mut buf = buf.borrow_mut();
+            let len = encode_title(title, &mut buf)?;
+            f(&buf)?;
+            crate::check_truncation(buf.len() - 1, len)
+        })
+    }
+
+    pub fn set_title(title: &OsStr) -> Result<(), TitleError> {
+        // Windows doesn't appear to have a userspace mechanism to name the current
+        // process.
+        //
+        // Try to set a console title, and in case we're not attached to one,
+        // follow PostgreSQL's lead and create a named event handle that can be
+        // found in Process Explorer, Process Hacker, etc.
+        with_encoded_title(title, set_title_raw_wide)
+    }
+
+    /// Set a title already encoded as NUL-terminated UTF-16.
+    pub fn set_title_raw_wide(title: &[u16]) -> Result<(), TitleError> {
+        #[cfg(feature = "windows-event-handle")]
+        let event = event::set_name(title);
+
+        set_console_title(title)?;
+
+        #[cfg(feature = "windows-event-handle")]
+        event?;
+
+        Ok(())
+    }
+
+    /// Set the console and event titles separately, an empty one putting back
+    /// the original console title, or leaving no event at all.
+    pub fn set_titles(console: &OsStr, event: &OsStr) -> Result<(), TitleError> {
+        // Encoded apart from the console title, which takes the scratch buffer.
+        #[cfg(feature = "windows-event-handle")]
+        let event = match event.is_empty() {
+            true => {
+                event::clear();
+                Ok(())
+            }
+            false => {
+                let mut buf = Vec::new();
+                encode_title(event, &mut buf).and_then(|len| {
+                    event::set_name(&buf)?;
+                    crate::check_truncation(buf.len() - 1, len)
+                })
+            }
+        };
+
+        #[cfg(not(feature = "windows-event-handle"))]
+        let _ = event;
+
+        match console.is_empty() {
+            true => reset_console_title()?,
+            false => with_encoded_title(console, set_console_title)?,
+        }
+
+        #[cfg(feature = "windows-event-handle")]
+        event?;
+
+        Ok(())
+    }
+
+    fn set_console_title(title: &[u16]) -> Result<(), TitleError> {
+        lazy_static::initialize(&ORIGINAL_CONSOLE_TITLE);
+        unsafe { SetConsoleTitleW(title.as_ptr()) };
+        set_vt_title(title)
+    }
+
+    static VT: AtomicBool = AtomicBool::new(false);
+
+    pub fn set_vt_enabled(enabled: bool) {
+        VT.store(enabled, Ordering::Relaxed);
+    }
+
+    /// Also write the title to stdout as an OSC sequence, if asked to and
+    /// stdout is a console processing them, as Windows Terminal's tabs don't
+    /// always follow SetConsoleTitleW().
+    ///
+    /// GetConsoleMode() fails for a redirected stdout, so escapes never end
+    /// up in a file or pipe.
+    fn set_vt_title(title: &[u16]) -> Result<(), TitleError> {
+        if !VT.load(Ordering::Relaxed) {
+            return Ok(());
+        }
+
+        let mut mode = 0;
+        let stdout = unsafe { GetStdHandle(STD_OUTPUT_HANDLE) };
+        if unsafe { GetConsoleMode(stdout, &mut mode) } == 0
+            || mode & ENABLE_VIRTUAL_TERMINAL_PROCESSING == 0
+        {
+            return Ok(());
+        }
+
+        let len = title.iter().position(|&c| c == 0).unwrap_or(title.len());
+        let mut stdout = std::io::stdout().lock();
+        stdout.write_all(&crate::osc_title(
+            &OsString::from_wide(&title[..len]),
+            usize::MAX,
+        ))?;
+        stdout.flush()?;
+        Ok(())
+    }
+
+    type SetThreadDescription = unsafe extern "system" fn(HANDLE, PCWSTR) -> HRESULT;
+
+    lazy_static! {
+        // The console title from before we first changed it, for reset_title(),
+        // or None if we didn't have a console then.
+        static ref ORIGINAL_CONSOLE_TITLE: Option<Vec<u16>> = console_title();
+
+        // Only available since Windows 10 1607, so look it up at runtime.
+        static ref SET_THREAD_DESCRIPTION: Option<SetThreadDescription> = unsafe {
+            system_fn("kernel32.dll", b"SetThreadDescription\0")
+                .map(|f| std::mem::transmute::<FARPROC, SetThreadDescription>(f))
+        };
+    }
+
+    /// Look up a function exported by `module`, one of kernel32.dll and
+    /// ntdll.dll, which are always loaded.
+    unsafe fn system_fn(module: &str, name: &[u8]) -> Option<FARPROC> {
+        let module: Vec<u16> = OsStr::new(module).encode_wide().chain(Some(0)).collect();
+        let module = GetModuleHandleW(module.as_ptr());
+        if module.is_null() {
+            return None;
+        }
+
+        Some(GetProcAddress(module, name.as_ptr() as *const i8)).filter(|f| !f.is_null())
+    }
+
+    pub fn set_thread_title(title: &OsStr) -> Result<(), TitleError> {
+        let set_thread_description = match *SET_THREAD_DESCRIPTION {
+            Some(f) => f,
+            None => return Err(TitleError::Unsupported),
+        };
+
+        with_encoded_title(title, |t| {
+            let hr = unsafe { set_thread_description(GetCurrentThread(), t.as_ptr()) };
+            if hr < 0 {
+                return Err(hresult_error(hr).into());
+            }
+            Ok(())
+        })
+    }
+
+    /// Turn a failed `HRESULT` into an error, as the Win32 error code inside it
+    /// where there is one.
+    fn hresult_error(hr: HRESULT) -> std::io::Error {
+        match HRESULT_FACILITY(hr) {
+            FACILITY_WIN32 => std::io::Error::from_raw_os_error(HRESULT_CODE(hr)),
+            _ => std::io::Error::other(format!("HRESULT {:#010x}", hr as u32)),
+        }
+    }
+
+    pub fn get_thread_title() -> Option<OsString> {
+        None
+    }
+
+    #[cfg(feature = "windows-event-handle")]
+    pub use self::event::{
+        clear as close_event, find as find_titles, name as event_name,
+        raw_handle as raw_event_handle, set_prefix,
+    };
+
+    pub fn set_title_signal_safe(_title: &CStr) -> Result<(), TitleError> {
+        // There are no signals to speak of, and anything we might do from
+        // a console control handler can take the ordinary path.
+        Err(TitleError::Unsupported)
+    }
+
+    pub fn reset_title() -> Result<(), TitleError> {
+        #[cfg(feature = "windows-event-handle")]
+        event::clear();
+
+        reset_console_title()
+    }
+
+    fn reset_console_title() -> Result<(), TitleError> {
+        if let Some(title) = ORIGINAL_CONSOLE_TITLE.as_ref() {
+            unsafe { SetConsoleTitleW(title.as_ptr()) };
+            set_vt_title(title)?;
+        }
+
+        Ok(())
+    }
+
+    /// The console's title, NUL-terminated, if we have a console.
+    ///
+    /// Something else may have set a longer title than we ever would, and
+    /// GetConsoleTitleW() only says how much it copied, not how much there
+    /// was, so keep growing the buffer while it comes back full.  Depending on
+    /// the version of Windows, a short buffer may instead be an error.
+    fn console_title() -> Option<Vec<u16>> {
+        // The documented limit is 64K bytes, NUL included.
+        const MAX_BUF: usize = 32 * 1024;
+
+        let mut buf = vec![0u16; MAX_CONSOLE_TITLE + 1];
+        loop {
+            let len = unsafe {
+                SetLastError(0);
+                GetConsoleTitleW(buf.as_mut_ptr(), buf.len() as u32)
+            } as usize;
+            let short = match len {
+                0 => ERROR_INSUFFICIENT_BUFFER == unsafe { GetLastError() },
+                len => len >= buf.len() - 1,
+            };
+
+            if short && buf.len() < MAX_BUF {
+                buf.resize(buf.len() * 2, 0);
+                continue;
+            }
+            if len == 0 {
+                return None;
+            }
+
+            buf.truncate(len.min(buf.len() - 1));
+            buf.push(0);
+            return Some(buf);
+        }
+    }
+
+    pub fn get_title() -> Option<OsString> {
+        if let Some(title) = console_title() {
+            return Some(OsString::from_wide(&title[..title.len() - 1]));
+        }
+
+        // No console, so fall back to whatever we last named our event handle.
+        #[cfg(feature = "windows-event-handle")]
+        return event::title();
+
+        #[cfg(not(feature = "windows-event-handle"))]
+        None
+    }
+
+    #[test]
+    fn set_title_sets_console_title_and_makes_a_handle() {
+        let _lock = crate::test_lock();
+        let title = "Pinkle, squirmy, blib, blab, blob";
+        crate::set_title(title);
+
+        let mut t: Vec<u16> = std::ffi::OsString::from(title).encode_wide().collect();
+        t.push(0);
+        let mut buf = vec![0; t.len()];
+        let len = unsafe { GetConsoleTitleW(buf.as_mut_ptr(), buf.len() as u32) };
+
+        assert_eq!(len, title.len() as u32, "length mismatch");
+        assert_eq!(buf, t, "buffer mismatch");
+        #[cfg(feature = "windows-event-handle")]
+        assert_eq!(event::name().unwrap(), title, "event handle missing");
+        assert_eq!(get_title().unwrap(), title);
+    }
+
+    #[cfg(feature = "windows-event-handle")]
+    #[test]
+    fn event_handle_can_be_opened_by_name() {
+        use winapi::um::handleapi::CloseHandle;
+        use winapi::um::synchapi::OpenEventW;
+        use winapi::um::winnt::SYNCHRONIZE;
+
+        let _lock = crate::test_lock();
+        crate::set_title_prefix("proctitle-test:");
+        crate::set_title("findable");
+        crate::set_title_prefix("");
+
+        let name = crate::event_handle_name().unwrap();
+        assert_eq!(name, "proctitle-test:findable");
+        assert!(crate::raw_event_handle().is_some());
+
+        let mut wide: Vec<u16> = name.encode_wide().collect();
+        wide.push(0);
+        let handle = unsafe { OpenEventW(SYNCHRONIZE, 0, wide.as_ptr()) };
+        assert!(!handle.is_null(), "couldn't open event by name");
+        unsafe { CloseHandle(handle) };
+    }
+
+    #[cfg(feature = "windows-event-handle")]
+    #[test]
+    fn set_windows_titles_sets_each_separately() {
+        let _lock = crate::test_lock();
+        crate::set_windows_titles("tab", "detailed status");
+        assert_eq!(crate::event_handle_name().unwrap(), "detailed status");
+        if has_console() {
+            assert_eq!(get_title().unwrap(), "tab");
+        }
+
+        crate::set_windows_titles("tab", "");
+        assert_eq!(crate::event_handle_name(), None);
+        crate::reset_title();
+    }
+
+    #[test]
+    fn set_title_raw_wide_needs_trailing_nul() {
+        let _lock = crate::test_lock();
+        let mut title: Vec<u16> = OsStr::new("raw and wide").encode_wide().collect();
+        crate::set_title("before");
+        crate::set_title_raw_wide(&title);
+        assert_eq!(get_title().unwrap(), "before");
+
+        title.push(0);
+        crate::set_title_raw_wide(&title);
+        assert_eq!(get_title().unwrap(), "raw and wide");
+    }
+
+    #[test]
+    fn hresult_error_unwraps_win32_codes() {
+        use winapi::shared::winerror::{ERROR_ACCESS_DENIED, E_NOTIMPL, HRESULT_FROM_WIN32};
+
+        let err = hresult_error(HRESULT_FROM_WIN32(ERROR_ACCESS_DENIED));
+        assert_eq!(err.raw_os_error(), Some(ERROR_ACCESS_DENIED as i32));
+
+        let err = hresult_error(E_NOTIMPL);
+        assert_eq!(err.raw_os_error(), None);
+        assert_eq!(err.to_string(), "HRESULT 0x80004001");
+    }
+
+    #[test]
+    fn has_console_matches_console_title() {
+        let _lock = crate::test_lock();
+        crate::set_title("Is anybody out there?");
+
+        let mut buf = vec![0u16; 64];
+        let len = unsafe { GetConsoleTitleW(buf.as_mut_ptr(), buf.len() as u32) };
+        assert_eq!(has_console(), len > 0);
+    }
+
+    #[test]
+    fn console_title_reads_titles_longer_than_ours() {
+        let _lock = crate::test_lock();
+        if !has_console() {
+            return;
+        }
+
+        // Longer than we'd ever set ourselves, so set it directly.
+        let long: Vec<u16> = OsStr::new(&"z".repeat(MAX_CONSOLE_TITLE * 3))
+            .encode_wide()
+            .chain(Some(0))
+            .collect();
+        unsafe { SetConsoleTitleW(long.as_ptr()) };
+
+        assert_eq!(console_title().unwrap(), long);
+        crate::reset_title();
+    }
+
+    #[test]
+    fn reset_title_restores_console_title() {
+        let _lock = crate::test_lock();
+        let original = ORIGINAL_CONSOLE_TITLE.as_ref().map(|t| &t[..t.len() - 1]);
+        crate::set_title("Temporary");
+        crate::reset_title();
+
+        let title = console_title();
+        assert_eq!(title.as_ref().map(|t| &t[..t.len() - 1]), original);
+    }
+
+    #[test]
+    fn set_thread_title_sets_thread_description() {
+        type GetThreadDescription = unsafe extern "system" fn(HANDLE, *mut *mut u16) -> HRESULT;
+
+        let get_thread_description =
+            match unsafe { system_fn("kernel32.dll", b"GetThreadDescription\0") } {
+                Some(f) => unsafe { std::mem::transmute::<FARPROC, GetThreadDescription>(f) },
+                None => return,
+            };
+
+        crate::set_thread_title("io-worker-3");
+
+        let mut desc = std::ptr::null_mut();
+        let hr = unsafe { get_thread_description(GetCurrentThread(), &mut desc) };
+        assert!(hr >= 0, "GetThreadDescription failed");
+
+        let len = (0..).take_while(|&i| unsafe { *desc.add(i) } != 0).count();
+        let title = OsString::from_wide(unsafe { std::slice::from_raw_parts(desc, len) });
+        unsafe { winapi::um::winbase::LocalFree(desc as _) };
+
+        assert_eq!(title, "io-worker-3");
+    }
+
+    #[test]
+    fn encode_title_keeps_surrogate_pairs_together() {
+        let title = format!("a{}", "\u{1F600}".repeat(600));
+        let mut t = Vec::new();
+        encode_title(OsStr::new(&title), &mut t).unwrap();
+
+        assert_eq!(t.len(), 1023 + 1);
+        assert_eq!(t[1022], 0xDE00, "lone surrogate emitted");
+        assert_eq!(
+            OsString::from_wide(&t[..t.len() - 1])
+                .into_string()
+                .unwrap(),
+            format!("a{}", "\u{1F600}".repeat(511))
+        );
+
+        let title = "\u{1F600}".repeat(600);
+        encode_title(OsStr::new(&title), &mut t).unwrap();
+        assert_eq!(t.len(), 1024 + 1);
+    }
+
+    #[test]
+    fn encode_title_rejects_interior_nul() {
+        match encode_title(OsStr::new("job\0secret"), &mut Vec::new()) {
+            Err(TitleError::InteriorNul) => (),
+            other => panic!("unexpected result: {:?}", other),
+        }
+    }
+}
+
+#[cfg(all(
+    feature = "std",
+    not(any(feature = "disabled", feature = "mock")),
+    any(target_os = "ios", target_os = "tvos", target_os = "watchos")
+))]
+mod imp {
+    // Apple's other platforms share much with macOS, but apps can't rename
+    // themselves, and their argv needn't be laid out as the macOS backend
+    // expects, so this is deliberately a no-op rather than left to chance.
+    use std::ffi::{CStr, OsStr, OsString};
+
+    use crate::TitleError;
+
+    pub const SUPPORTED: bool = false;
+
+    pub fn mechanism() -> crate::Mechanism {
+        crate::Mechanism::Noop
+    }
+
+    pub fn max_title_len() -> Option<usize> {
+        None
+    }
+
+    pub fn set_title(_title: &OsStr) -> Result<(), TitleError> {
+        Err(TitleError::Unsupported)
+    }
+
+    pub fn set_thread_title(_title: &OsStr) -> Result<(), TitleError> {
+        Err(TitleError::Unsupported)
+    }
+
+    pub fn get_thread_title() -> Option<OsString> {
+        None
+    }
+
+    pub fn reset_title() -> Result<(), TitleError> {
+        Err(TitleError::Unsupported)
+    }
+
+    pub fn set_title_signal_safe(_title: &CStr) -> Result<(), TitleError> {
+        Err(TitleError::Unsupported)
+    }
+
+    pub fn get_title() -> Option<OsString> {
+        None
+    }
+
+    #[test]
+    fn is_supported_is_false() {
+        const _: () = assert!(!crate::is_supported());
+        crate::set_title("Nobody home");
+        assert_eq!(crate::get_title(), None);
+    }
+}
+
+#[cfg(all(
+    feature = "std",
+    not(any(feature = "disabled", feature = "mock")),
+    target_arch = "wasm32"
+))]
+mod imp {
+    // Neither WASI nor the browser have any notion of a process title to set,
+    // so this is a no-op.  Should WASI ever gain one, here's where it goes.
+    use std::ffi::{CStr, OsStr, OsString};
+
+    use crate::TitleError;
+
+    pub const SUPPORTED: bool = false;
+
+    pub fn mechanism() -> crate::Mechanism {
+        crate::Mechanism::Noop
+    }
+
+    pub fn max_title_len() -> Option<usize> {
+        None
+    }
+
+    pub fn set_title(_title: &OsStr) -> Result<(), TitleError> {
+        Err(TitleError::Unsupported)
+    }
+
+    pub fn set_thread_title(_title: &OsStr) -> Result<(), TitleError> {
+        Err(TitleError::Unsupported)
+    }
+
+    pub fn get_thread_title() -> Option<OsString> {
+        None
+    }
+
+    pub fn reset_title() -> Result<(), TitleError> {
+        Err(TitleError::Unsupported)
+    }
+
+    pub fn set_title_signal_safe(_title: &CStr) -> Result<(), TitleError> {
+        Err(TitleError::Unsupported)
+    }
+
+    pub fn get_title() -> Option<OsString> {
+        None
+    }
+
+    #[test]
+    fn is_supported_is_false() {
+        const _: () = assert!(!crate::is_supported());
+        crate::set_title("Nobody home");
+        assert_eq!(crate::get_title(), None);
+    }
+}
+
+#[cfg(all(feature = "std", feature = "mock", not(feature = "disabled")))]
+mod imp {
+    // Keeps the title in memory rather than touching the process, so tests of
+    // code setting titles can check them on any platform.  It stands in for
+    // the platform-specific extras too, so it builds wherever the real thing
+    // would.
+    use std::ffi::{CStr, OsStr, OsString};
+    use std::sync::Mutex;
+
+    use crate::TitleError;
+
+    static TITLE: Mutex<Option<OsString>> = Mutex::new(None);
+
+    pub const SUPPORTED: bool = true;
+
+    pub fn mechanism() -> crate::Mechanism {
+        crate::Mechanism::Mock
+    }
+
+    pub fn max_title_len() -> Option<usize> {
+        Some(usize::MAX)
+    }
+
+    pub fn set_title(title: &OsStr) -> Result<(), TitleError> {
+        // Nothing's ever truncated, but NULs are refused like everywhere else.
+        if title.as_encoded_bytes().contains(&0) {
+            return Err(TitleError::InteriorNul);
+        }
+
+        *TITLE.lock().unwrap_or_else(|e| e.into_inner()) = Some(title.to_owned());
+        Ok(())
+    }
+
+    pub fn set_thread_title(_title: &OsStr) -> Result<(), TitleError> {
+        Ok(())
+    }
+
+    pub fn get_thread_title() -> Option<OsString> {
+        None
+    }
+
+    pub fn reset_title() -> Result<(), TitleError> {
+        *TITLE.lock().unwrap_or_else(|e| e.into_inner()) = None;
+        Ok(())
+    }
+
+    pub fn set_title_signal_safe(_title: &CStr) -> Result<(), TitleError> {
+        Err(TitleError::Unsupported)
+    }
+
+    pub fn get_title() -> Option<OsString> {
+        TITLE.lock().unwrap_or_else(|e| e.into_inner()).clone()
+    }
+
+    #[cfg(target_os = "freebsd")]
+    pub fn set_title_fast(title: &OsStr) -> Result<(), TitleError> {
+        set_title(title)
+    }
+
+    #[cfg(any(
+        target_os = "freebsd",
+        target_os = "dragonfly",
+        target_os = "netbsd",
+        target_os = "openbsd",
+        target_os = "bitrig",
+        target_os = "linux",
+        target_os = "android",
+    ))]
+    pub fn set_prefix_enabled(_enabled: bool) {}
+
+    #[cfg(any(
+        target_os = "freebsd",
+        target_os = "dragonfly",
+        target_os = "netbsd",
+        target_os = "openbsd",
+        target_os = "bitrig",
+        target_os = "linux",
+        target_os = "android",
+    ))]
+    pub fn rendered(title: &OsStr) -> OsString {
+        title.to_owned()
+    }
+
+    #[cfg(any(
+        target_os = "freebsd",
+        target_os = "dragonfly",
+        target_os = "netbsd",
+        target_os = "openbsd",
+        target_os = "bitrig",
+    ))]
+    pub fn set_title_raw(title: &CStr) -> Result<(), TitleError> {
+        use std::os::unix::ffi::OsStrExt;
+        set_title(OsStr::from_bytes(title.to_bytes()))
+    }
+
+    #[cfg(any(target_os = "linux", target_os = "android"))]
+    pub fn set_title_with_mode(
+        _mode: crate::LinuxTitleMode,
+        title: &OsStr,
+    ) -> Result<(), TitleError> {
+        set_title(title)
+    }
+
+    #[cfg(any(target_os = "linux", target_os = "android"))]
+    pub fn set_default_mode(_mode: crate::LinuxTitleMode) {}
+
+    #[cfg(windows)]
+    pub fn has_console() -> bool {
+        false
+    }
+
+    #[cfg(windows)]
+    pub fn set_vt_enabled(_enabled: bool) {}
+
+    #[cfg(windows)]
+    pub fn set_title_raw_wide(title: &[u16]) -> Result<(), TitleError> {
+        use std::os::windows::ffi::OsStringExt;
+        let len = title.iter().position(|&c| c == 0).unwrap_or(title.len());
+        set_title(&OsString::from_wide(&title[..len]))
+    }
+
+    #[cfg(windows)]
+    pub fn set_titles(console: &OsStr, _event: &OsStr) -> Result<(), TitleError> {
+        match console.is_empty() {
+            true => reset_title(),
+            false => set_title(console),
+        }
+    }
+
+    #[cfg(all(windows, feature = "windows-event-handle"))]
+    pub fn set_prefix(_prefix: &OsStr) {}
+
+    #[cfg(all(windows, feature = "windows-event-handle"))]
+    pub fn event_name() -> Option<OsString> {
+        None
+    }
+
+    #[cfg(all(windows, feature = "windows-event-handle"))]
+    pub fn raw_event_handle() -> Option<std::os::windows::io::RawHandle> {
+        None
+    }
+
+    #[cfg(all(windows, feature = "windows-event-handle"))]
+    pub fn find_titles(_prefix: &OsStr) -> std::io::Result<Vec<OsString>> {
+        Ok(Vec::new())
+    }
+
+    #[cfg(all(windows, feature = "windows-event-handle"))]
+    pub fn close_event() {}
+
+    #[test]
+    fn mock_remembers_last_title() {
+        let _lock = crate::test_lock();
+        crate::set_title("x");
+        assert_eq!(crate::get_title(), Some("x".into()));
+        assert_eq!(crate::platform_info().mechanism, crate::Mechanism::Mock);
+
+        crate::reset_title();
+        assert_eq!(crate::get_title(), None);
+    }
+}
+
+#[cfg(all(
+    feature = "std",
+    any(
+        feature = "disabled",
+        all(
+            not(feature = "mock"),
+            not(any(
+                target_os = "freebsd",
+                target_os = "dragonfly",
+                target_os = "netbsd",
+                target_os = "openbsd",
+                target_os = "bitrig",
+                target_os = "linux",
+                target_os = "android",
+                target_os = "macos",
+                target_os = "solaris",
+                target_os = "illumos",
+                target_os = "haiku",
+                target_os = "hurd",
+                target_os = "nto",
+                target_os = "windows",
+                target_os = "ios",
+                target_os = "tvos",
+                target_os = "watchos",
+                target_arch = "wasm32"
+            ))
+        )
+    )
+))]
+mod imp {
+    // Also used on every platform with the `disabled` feature, so it carries
+    // no-op versions of the platform-specific extras too.
+    use std::ffi::{CStr, OsStr, OsString};
+
+    use crate::TitleError;
+
+    pub const SUPPORTED: bool = false;
+
+    pub fn mechanism() -> crate::Mechanism {
+        crate::Mechanism::Noop
+    }
+
+    pub fn max_title_len() -> Option<usize> {
+        None
+    }
+
+    pub fn set_title(_title: &OsStr) -> Result<(), TitleError> {
+        Err(TitleError::Unsupported)
+    }
+
+    pub fn set_thread_title(_title: &OsStr) -> Result<(), TitleError> {
+        Err(TitleError::Unsupported)
+    }
+
+    pub fn get_thread_title() -> Option<OsString> {
+        None
+    }
+
+    pub fn reset_title() -> Result<(), TitleError> {
+        Err(TitleError::Unsupported)
+    }
+
+    pub fn set_title_signal_safe(_title: &CStr) -> Result<(), TitleError> {
+        Err(TitleError::Unsupported)
+    }
+
+    pub fn get_title() -> Option<OsString> {
+        None
+    }
+
+    #[cfg(target_os = "freebsd")]
+    pub fn set_title_fast(_title: &OsStr) -> Result<(), TitleError> {
+        Err(TitleError::Unsupported)
+    }
+
+    #[cfg(any(
+        target_os = "freebsd",
+        target_os = "dragonfly",
+        target_os = "netbsd",
+        target_os = "openbsd",
+        target_os = "bitrig",
+        target_os = "linux",
+        target_os = "android",
+    ))]
+    pub fn set_prefix_enabled(_enabled: bool) {}
+
+    #[cfg(any(
+        target_os = "freebsd",
+        target_os = "dragonfly",
+        target_os = "netbsd",
+        target_os = "openbsd",
+        target_os = "bitrig",
+        target_os = "linux",
+        target_os = "android",
+    ))]
+    pub fn rendered(title: &OsStr) -> OsString {
+        title.to_owned()
+    }
+
+    #[cfg(any(
+        target_os = "freebsd",
+        target_os = "dragonfly",
+        target_os = "netbsd",
+        target_os = "openbsd",
+        target_os = "bitrig",
+    ))]
+    pub fn set_title_raw(_title: &CStr) -> Result<(), TitleError> {
+        Err(TitleError::Unsupported)
+    }
+
+    #[cfg(any(target_os = "linux", target_os = "android"))]
+    pub fn set_title_with_mode(
+        _mode: crate::LinuxTitleMode,
+        _title: &OsStr,
+    ) -> Result<(), TitleError> {
+        Err(TitleError::Unsupported)
+    }
+
+    #[cfg(any(target_os = "linux", target_os = "android"))]
+    pub fn set_default_mode(_mode: crate::LinuxTitleMode) {}
+
+    #[cfg(windows)]
+    pub fn has_console() -> bool {
+        false
+    }
+
+    #[cfg(windows)]
+    pub fn set_vt_enabled(_enabled: bool) {}
+
+    #[cfg(windows)]
+    pub fn set_title_raw_wide(_title: &[u16]) -> Result<(), TitleError> {
+        Err(TitleError::Unsupported)
+    }
+
+    #[cfg(windows)]
+    pub fn set_titles(_console: &OsStr, _event: &OsStr) -> Result<(), TitleError> {
+        Err(TitleError::Unsupported)
+    }
+
+    #[cfg(all(windows, feature = "windows-event-handle"))]
+    pub fn set_prefix(_prefix: &OsStr) {}
+
+    #[cfg(all(windows, feature = "windows-event-handle"))]
+    pub fn event_name() -> Option<OsString> {
+        None
+    }
+
+    #[cfg(all(windows, feature = "windows-event-handle"))]
+    pub fn raw_event_handle() -> Option<std::os::windows::io::RawHandle> {
+        None
+    }
+
+    #[cfg(all(windows, feature = "windows-event-handle"))]
+    pub fn find_titles(_prefix: &OsStr) -> std::io::Result<Vec<OsString>> {
+        Ok(Vec::new())
+    }
+
+    #[cfg(all(windows, feature = "windows-event-handle"))]
+    pub fn close_event() {}
+
+    #[cfg(feature = "disabled")]
+    #[test]
+    fn disabled_is_unsupported() {
+        const _: () = assert!(!crate::is_supported());
+        assert_eq!(crate::platform_info().mechanism, crate::Mechanism::Noop);
+        crate::set_title("Nobody home");
+        assert_eq!(crate::get_title(), None);
+    }
+}
+
+#[cfg(not(feature = "std"))]
+mod imp {
+    // Without std there's nowhere to keep track of argv or the original title,
+    // so this only does what a single call can: renaming the process on Linux,
+    // or setproctitle() on the BSDs.
+    use core::ffi::CStr;
+
+    pub const SUPPORTED: bool = cfg!(all(
+        not(feature = "disabled"),
+        any(
+            target_os = "linux",
+            target_os = "android",
+            target_os = "freebsd",
+            target_os = "dragonfly",
+            target_os = "netbsd",
+            target_os = "openbsd",
+            target_os = "bitrig",
+        )
+    ));
+
+    pub fn set_title_cstr(title: &CStr) {
+        #[cfg(all(
+            not(feature = "disabled"),
+            any(target_os = "linux", target_os = "android")
+        ))]
+        unsafe {
+            libc::prctl(libc::PR_SET_NAME, title.as_ptr(), 0, 0, 0);
+        }
+
+        #[cfg(all(
+            not(feature = "disabled"),
+            any(
+                target_os = "freebsd",
+                target_os = "dragonfly",
+                target_os = "netbsd",
+                target_os = "openbsd",
+                target_os = "bitrig",
+            )
+        ))]
+        unsafe {
+            setproctitle(b"-%s\0".as_ptr(), title.as_ptr());
+        }
+
+        let _ = title;
+    }
+
+    #[cfg(all(
+        not(feature = "disabled"),
+        any(
+            target_os = "freebsd",
+            target_os = "dragonfly",
+            target_os = "netbsd",
+            target_os = "openbsd",
+            target_os = "bitrig",
+        )
+    ))]
+    extern "C" {
+        fn setproctitle(fmt: *const u8, ...);
+    }
+
+    #[cfg(all(not(feature = "disabled"), target_os = "linux"))]
+    #[test]
+    fn set_title_cstr_renames_thread() {
+        crate::set_title_cstr(CStr::from_bytes_with_nul(b"no_std title\0").unwrap());
+
+        let mut buf = [0u8; crate::MAX_LINUX_COMM_LEN];
+        unsafe { libc::prctl(libc::PR_GET_NAME, buf.as_mut_ptr(), 0, 0, 0) };
+        assert_eq!(&buf[..13], b"no_std title\0");
+    }
+}
+
+#[cfg(all(feature = "systemd", unix))]
+mod systemd;
+#[cfg(all(feature = "systemd", unix))]
+pub use crate::systemd::{set_status, set_status_and_title};
+
+#[cfg(all(feature = "std", unix))]
+mod command;
+#[cfg(all(feature = "std", unix))]
+pub use crate::command::CommandExt;
+
+#[cfg(feature = "std")]
+mod ticker;
+#[cfg(feature = "std")]
+pub use crate::ticker::{spawn_title_ticker, TitleTicker};
+#[cfg(feature = "std")]
+mod title;
+#[cfg(feature = "std")]
+pub use crate::title::Title;
+
+#[cfg(feature = "tracing")]
+mod tracing_layer;
+#[cfg(feature = "tracing")]
+pub use crate::tracing_layer::TitleLayer;
+
+use core::sync::atomic::{AtomicBool, Ordering};
+#[cfg(feature = "std")]
+use std::error::Error;
+#[cfg(feature = "std")]
+use std::ffi::{CStr, NulError, OsStr, OsString};
+#[cfg(feature = "std")]
+use std::fmt;
+#[cfg(feature = "std")]
+use std::io;
+#[cfg(feature = "std")]
+use std::path::Path;
+#[cfg(feature = "std")]
+use std::sync::Mutex;
+#[cfg(feature = "std")]
+use std::time::{Duration, Instant};
+
+#[cfg(feature = "std")]
+use lazy_static::lazy_static;
+
+#[cfg(feature = "std")]
+lazy_static! {
+    // Held while changing the title, so replace_title() can't be interleaved
+    // with another change.
+    static ref TITLE_LOCK: Mutex<()> = Mutex::new(());
+    static ref TITLE_STACK: Mutex<Vec<OsString>> = Mutex::new(Vec::new());
+    static ref BASE_TITLE: Mutex<OsString> = Mutex::new(default_base_title());
+    // The title most recently set, as it was asked for, empty after a reset.
+    // Titles that were rejected outright never get here.
+    static ref LAST_TITLE: Mutex<OsString> = Mutex::new(OsString::new());
+}
+
+// Cleared by set_enabled(false) to silence title changes at runtime.
+static ENABLED: AtomicBool = AtomicBool::new(true);
+
+// Set by clamp_terminal_title(true) to fit terminal titles to the window.
+#[cfg(all(feature = "std", unix))]
+static CLAMP_TERMINAL_TITLE: AtomicBool = AtomicBool::new(false);
+
+/// The size of the Linux kernel's thread name buffer, `TASK_COMM_LEN`,
+/// including a trailing NUL.
+///
+/// Process names shown by `top` and `ps -o comm` are truncated to one less.
+pub const MAX_LINUX_COMM_LEN: usize = 16;
+
+/// The longest Windows console title, in UTF-16 code units, that `proctitle`
+/// will set.
+pub const MAX_CONSOLE_TITLE: usize = 1024;
+
+/// The reasons a process title might not be set.
+#[cfg(feature = "std")]
+#[derive(Debug)]
+pub enum TitleError {
+    /// The title contained a NUL byte, which the platform can't represent.
+    InteriorNul,
+    /// There's no mechanism for setting a title on this platform.
+    Unsupported,
+    /// The operating system refused to set the title.
+    Os(io::Error),
+    /// The title was set, but had to be cut short to fit.
+    ///
+    /// Lengths are counted as [`max_title_len`] counts them.
+    Truncated {
+        /// How much of the title was used.
+        written: usize,
+        /// The length of the title as given.
+        requested: usize,
+    },
+}
+
+/// Report a title of length `requested` that was cut down to `written`.
+#[cfg(feature = "std")]
+#[allow(dead_code)]
+fn check_truncation(written: usize, requested: usize) -> Result<(), TitleError> {
+    if written < requested {
+        Err(TitleError::Truncated { written, requested })
+    } else {
+        Ok(())
+    }
+}
+
+#[cfg(feature = "std")]
+impl fmt::Display for TitleError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TitleError::InteriorNul => write!(f, "title contains an interior NUL byte"),
+            TitleError::Unsupported => write!(f, "process titles are unsupported on this platform"),
+            TitleError::Os(e) => write!(f, "failed to set process title: {}", e),
+            TitleError::Truncated { written, requested } => write!(
+                f,
+                "process title truncated from {} to {}",
+                requested, written
+            ),
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl Error for TitleError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            TitleError::Os(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl From<io::Error> for TitleError {
+    fn from(e: io::Error) -> Self {
+        TitleError::Os(e)
+    }
+}
+
+#[cfg(feature = "std")]
+impl From<NulError> for TitleError {
+    fn from(_: NulError) -> Self {
+        TitleError::InteriorNul
+    }
+}
+
+/// Set a process title, or some approximation of it, if possible.
+///
+/// Anything that can be viewed as an [`OsStr`] will do, including paths:
+///
+/// ```
+/// use std::path::Path;
+/// proctitle::set_title(Path::new("/var/spool/mqueue/qfA12345"));
+/// ```
+///
+/// An empty title puts back the original, as [`reset_title`], on every
+/// platform, rather than leaving a blank entry in `ps`.  The same goes for the
+/// other ways of setting a title, apart from [`set_title_signal_safe`].
+///
+/// Threads may set titles at the same time: each change is made whole under a
+/// lock, so the title ends up as one of them and never a mix.
+#[cfg(feature = "std")]
+pub fn set_title<T: AsRef<OsStr>>(title: T) {
+    let _ = set_title_checked(title);
+}
+
+/// Set a process title from raw bytes, as [`set_title()`].
+///
+/// Titles containing NUL bytes are ignored.
+#[cfg(all(feature = "std", unix))]
+pub fn set_title_bytes(title: &[u8]) {
+    use std::os::unix::ffi::OsStrExt;
+    set_title(OsStr::from_bytes(title));
+}
+
+/// The environment variable [`init_from_env`] takes a title from.
+#[cfg(feature = "std")]
+pub const TITLE_ENV_VAR: &str = "PROCTITLE";
+
+/// Set a process title from the `PROCTITLE` environment variable, if it's set
+/// and not empty, returning whether it was.
+///
+/// This lets whoever starts the process label it from outside, such as an
+/// orchestrator naming each replica.  `proctitle` never reads the environment
+/// by itself, so call this early in `main` to opt in.
+///
+/// ```
+/// if !proctitle::init_from_env() {
+///     proctitle::set_title("myapp");
+/// }
+/// ```
+#[cfg(feature = "std")]
+pub fn init_from_env() -> bool {
+    init_from_env_var(TITLE_ENV_VAR)
+}
+
+/// Set a process title from the given environment variable, as
+/// [`init_from_env`].
+#[cfg(feature = "std")]
+pub fn init_from_env_var<K: AsRef<OsStr>>(key: K) -> bool {
+    match std::env::var_os(key) {
+        Some(title) if !title.is_empty() => {
+            set_title(title);
+            true
+        }
+        _ => false,
+    }
+}
+
+/// Put the title last set in the `PROCTITLE` environment variable, or remove
+/// it if the title's been reset, for [`init_from_env`] to pick up again.
+///
+/// A process that re-execs itself, such as for a zero-downtime upgrade, starts
+/// over with its original arguments, so call this just before `exec()` to
+/// carry the title across:
+///
+/// ```no_run
+/// # #[cfg(unix)] {
+/// use std::os::unix::process::CommandExt;
+///
+/// proctitle::export_title_env();
+/// let err = std::process::Command::new(std::env::current_exe().unwrap()).exec();
+/// # }
+/// ```
+///
+/// As with [`std::env::set_var`], avoid calling this while other threads may
+/// be reading the environment.
+#[cfg(feature = "std")]
+pub fn export_title_env() {
+    let last = LAST_TITLE.lock().unwrap_or_else(|e| e.into_inner());
+    match last.is_empty() {
+        true => std::env::remove_var(TITLE_ENV_VAR),
+        false => std::env::set_var(TITLE_ENV_VAR, &*last),
+    }
+}
+
+/// Set a process title, as [`set_title()`], but only the first time this is
+/// called.
+///
+/// Once a title has been set this way, later calls return without doing
+/// anything, whoever makes them, so a library can name the process at startup
+/// without clobbering a title the program chose for itself first.  A call that
+/// sets nothing, because titles are off with [`set_enabled`] or the title is
+/// rejected, such as for containing a NUL, doesn't count, so the next call
+/// still gets its turn.  Other functions, such as [`set_title()`], still
+/// change the title as usual.
+#[cfg(feature = "std")]
+pub fn set_title_once<T: AsRef<OsStr>>(title: T) {
+    static DONE: Mutex<bool> = Mutex::new(false);
+
+    let mut done = DONE.lock().unwrap_or_else(|e| e.into_inner());
+    if *done || !is_enabled() {
+        return;
+    }
+
+    *done = was_set(&set_title_checked(title));
+}
+
+/// Set a process title, as [`set_title()`], followed by the process ID, so it
+/// reads `worker [12345]`.
+///
+/// This helps tell apart many instances of the same program, such as in a
+/// supervisor's process list.  On Windows the ID ends up in both the console
+/// title and the event name.  Where titles are truncated, as in the 15-byte
+/// Linux process name, the ID is first to go.  An empty title resets the
+/// title, as with [`set_title()`], without adding the ID.
+#[cfg(feature = "std")]
+pub fn set_title_with_pid<T: AsRef<OsStr>>(title: T) {
+    let title = title.as_ref();
+    if title.is_empty() {
+        return set_title(title);
+    }
+
+    let mut title = title.to_owned();
+    title.push(format!(" [{}]", std::process::id()));
+    set_title(title);
+}
+
+/// Set a process title, as [`set_title()`], and adjust how readily the OOM
+/// killer picks this process, by writing `/proc/self/oom_score_adj`.
+///
+/// The adjustment runs from -1000, never kill, to 1000, kill first, and lowering
+/// it beyond where it started requires `CAP_SYS_RESOURCE`.  The title is set
+/// whether or not the adjustment is accepted.
+///
+/// ```no_run
+/// proctitle::set_title_and_oom_adj("background compaction", 500).unwrap();
+/// ```
+///
+/// Requires the `oom-score-adj` feature, and does nothing but set the title
+/// under `mock` or `disabled`.
+#[cfg(all(
+    feature = "oom-score-adj",
+    any(target_os = "linux", target_os = "android")
+))]
+pub fn set_title_and_oom_adj<T: AsRef<OsStr>>(title: T, oom_score_adj: i16) -> io::Result<()> {
+    set_title(title);
+
+    if cfg!(any(feature = "disabled", feature = "mock")) {
+        return Ok(());
+    }
+
+    std::fs::write("/proc/self/oom_score_adj", oom_score_adj.to_string())
+}
+
+/// Set a process title from [`format_args!`], as [`set_title()`].
+///
+/// The title is formatted into a buffer kept for the purpose, rather than a
+/// new `String` each time.  Like any other title, it's never itself taken as
+/// a format string by the platform, so a `%` in it is just a `%`.
+///
+/// ```
+/// # let (done, rate) = (100, 5);
+/// proctitle::set_title_fmt(format_args!("{}% done, rate: {}%/s", done, rate));
+/// ```
+#[cfg(feature = "std")]
+pub fn set_title_fmt(args: fmt::Arguments<'_>) {
+    use std::cell::RefCell;
+    use std::fmt::Write;
+
+    thread_local! {
+        // Clippy doesn't see the const where thread locals are emulated.
+        #[allow(clippy::missing_const_for_thread_local)]
+        static FMT_BUF: RefCell<String> = const { RefCell::new(String::new()) };
+    }
+
+    if let Some(title) = args.as_str() {
+        return set_title(title);
+    }
+
+    FMT_BUF.with(|buf| match buf.try_borrow_mut() {
+        Ok(mut buf) => {
+            buf.clear();
+            let _ = buf.write_fmt(args);
+            set_title(&*buf);
+        }
+        // Formatting an argument set a title of its own.
+        Err(_) => set_title(fmt::format(args)),
+    })
+}
+
+/// Set a process title, as [`set_title()`], after replacing any control
+/// characters with `?`.
+///
+/// Use this for titles including untrusted input, such as request URLs, where
+/// a stray `\r` or `\n` would garble `ps` output, or an escape sequence like
+/// `\x1b[` could be interpreted by the terminal of whoever runs it.  Titles
+/// which aren't valid Unicode have the offending parts replaced with `�`.
+///
+/// ```
+/// proctitle::set_title_sanitized("GET /\x1b[2J\r\n");
+/// ```
+#[cfg(feature = "std")]
+pub fn set_title_sanitized<T: AsRef<OsStr>>(title: T) {
+    set_title(sanitize(title.as_ref()));
+}
+
+#[cfg(feature = "std")]
+fn sanitize(title: &OsStr) -> String {
+    title
+        .to_string_lossy()
+        .chars()
+        .map(|c| if c.is_control() { '?' } else { c })
+        .collect()
+}
+
+/// `ESC ] 0 ; title BEL`, which sets a terminal's window or tab title, with
+/// any control characters that could end it early sanitized away, and the
+/// title cut to at most `width` characters.
+#[cfg(all(
+    feature = "std",
+    any(unix, all(windows, not(any(feature = "disabled", feature = "mock"))))
+))]
+fn osc_title(title: &OsStr, width: usize) -> Vec<u8> {
+    let title = sanitize(title);
+    let end = title
+        .char_indices()
+        .nth(width)
+        .map_or(title.len(), |(i, _)| i);
+
+    let mut seq = b"\x1b]0;".to_vec();
+    seq.extend_from_slice(&title.as_bytes()[..end]);
+    seq.push(0x07);
+    seq
+}
+
+/// The width of the terminal on stdout in columns, from `TIOCGWINSZ`, or
+/// failing that, `$COLUMNS`.
+#[cfg(all(feature = "std", unix))]
+fn terminal_width() -> Option<usize> {
+    let mut size: libc::winsize = unsafe { std::mem::zeroed() };
+    if unsafe { libc::ioctl(libc::STDOUT_FILENO, libc::TIOCGWINSZ, &mut size) } == 0
+        && size.ws_col > 0
+    {
+        return Some(size.ws_col.into());
+    }
+
+    std::env::var("COLUMNS")
+        .ok()
+        .and_then(|columns| columns.trim().parse().ok())
+        .filter(|&columns| columns > 0)
+}
+
+/// Set the title of the terminal window or tab the process is running in, as
+/// opposed to the process title seen by `ps`.
+///
+/// This writes an `ESC ] 0 ;` escape sequence to stdout, which xterm and most
+/// terminals since understand, with any control characters in the title
+/// replaced by `?`.  If stdout isn't a terminal, such as when it's redirected
+/// to a file or pipe, nothing is written, so output isn't corrupted.  Nor is
+/// anything written with the `mock` feature.
+///
+/// Long titles are written in full unless [`clamp_terminal_title`] is on.
+///
+/// ```
+/// proctitle::set_terminal_title("build: 3/10");
+/// ```
+#[cfg(all(feature = "std", unix))]
+pub fn set_terminal_title<T: AsRef<OsStr>>(title: T) {
+    use std::io::{IsTerminal, Write};
+
+    let stdout = io::stdout();
+    if cfg!(any(feature = "disabled", feature = "mock")) || !is_enabled() || !stdout.is_terminal() {
+        return;
+    }
+
+    let width = match CLAMP_TERMINAL_TITLE.load(Ordering::Relaxed) {
+        true => terminal_width().unwrap_or(usize::MAX),
+        false => usize::MAX,
+    };
+
+    let mut stdout = stdout.lock();
+    let _ = stdout
+        .write_all(&osc_title(title.as_ref(), width))
+        .and_then(|()| stdout.flush());
+}
+
+/// Choose whether [`set_terminal_title`] cuts titles to the width of the
+/// terminal, so they don't wrap oddly in terminals that show them in full.
+///
+/// The width comes from the terminal on stdout, or `$COLUMNS` if it can't be
+/// asked, and is counted in characters, so wide characters may still overflow.
+/// This is off by default, and has no effect on process titles.
+#[cfg(all(feature = "std", unix))]
+pub fn clamp_terminal_title(enabled: bool) {
+    CLAMP_TERMINAL_TITLE.store(enabled, Ordering::Relaxed);
+}
+
+/// Set a process title from a format string, as [`set_title()`].
+///
+/// ```
+/// # let (id, n) = (3, 42);
+/// proctitle::set_title!("worker {}: {} items", id, n);
+/// ```
+///
+/// A lone string literal is formatted just the same, as with [`format!`], so
+/// `set_title!("job {n}")` captures `n` and `{{` is a literal brace.  Any other
+/// lone expression is passed to [`set_title()`] as-is, without formatting.
+#[cfg(feature = "std")]
+#[macro_export]
+macro_rules! set_title {
+    ($fmt:literal $(,)?) => {
+        $crate::set_title_fmt(format_args!($fmt))
+    };
+    ($title:expr $(,)?) => {
+        $crate::set_title($title)
+    };
+    ($fmt:expr, $($arg:tt)+) => {
+        $crate::set_title_fmt(format_args!($fmt, $($arg)+))
+    };
+}
+
+/// Set a process title naming the function it's called from, such as
+/// `myapp::pipeline::compact`, optionally followed by a formatted message.
+///
+/// ```
+/// fn compact(shard: u32) {
+///     proctitle::set_title_here!();
+///     proctitle::set_title_here!("shard {}", shard);
+/// }
+/// # compact(7);
+/// ```
+///
+/// The second call sets `rust_out::compact: shard 7`, or similar.  Within a
+/// closure, the name ends with `{{closure}}`.
+#[cfg(feature = "std")]
+#[macro_export]
+macro_rules! set_title_here {
+    () => {
+        $crate::set_title($crate::__here!())
+    };
+    ($($arg:tt)+) => {
+        $crate::set_title(format!("{}: {}", $crate::__here!(), format_args!($($arg)+)))
+    };
+}
+
+/// The path of the enclosing function, from the type name of one nested in it.
+#[cfg(feature = "std")]
+#[doc(hidden)]
+#[macro_export]
+macro_rules! __here {
+    () => {{
+        fn __here() {}
+        $crate::__fn_path(__here)
+    }};
+}
+
+#[cfg(feature = "std")]
+#[doc(hidden)]
+pub fn __fn_path<F>(_: F) -> &'static str {
+    let name = std::any::type_name::<F>();
+    name.strip_suffix("::__here").unwrap_or(name)
+}
+
+/// Log a message with the [`log`] crate, and also use it as the process title.
+///
+/// ```
+/// # let n = 42;
+/// proctitle::log_and_title!(log::Level::Info, "compacting {} segments", n);
+/// ```
+///
+/// The message is only formatted once.  Requires the `log` feature.
+///
+/// [`log`]: https://docs.rs/log
+#[cfg(feature = "log")]
+#[macro_export]
+macro_rules! log_and_title {
+    ($level:expr, $($arg:tt)+) => {{
+        let message = format!($($arg)+);
+        $crate::__log::log!($level, "{}", message);
+        $crate::set_title(message);
+    }};
+}
+
+#[cfg(feature = "log")]
+#[doc(hidden)]
+pub use log as __log;
+
+/// Set a process title, or some approximation of it, reporting why if this
+/// wasn't possible.
+///
+/// If the title is too long for the platform, it's still set as far as it
+/// fits, but [`TitleError::Truncated`] is returned.
+///
+/// On Linux and Android, if the process arguments can't be found or aren't
+/// writable, only the 15-byte process name is set.  Longer titles are then
+/// reported as truncated to it, but shorter ones fit and give `Ok`, so to find
+/// out whether this has happened, check for [`Mechanism::Prctl`] from
+/// [`platform_info`], which gives it from then on.
+///
+/// If naming the process itself fails, as when a seccomp filter blocks
+/// `prctl(PR_SET_NAME)`, the error from it is returned as [`TitleError::Os`],
+/// such as `EPERM`, even though the command line may have been updated.
+///
+/// On Windows, failing to create the named event handle is reported as
+/// [`TitleError::Os`].  `ERROR_ALREADY_EXISTS` means the title was set, but an
+/// event of that name already existed, probably belonging to another process
+/// with the same title, and is now shared with it.
+#[cfg(feature = "std")]
+pub fn set_title_checked<T: AsRef<OsStr>>(title: T) -> Result<(), TitleError> {
+    if !is_enabled() {
+        return Ok(());
+    }
+
+    let _lock = title_lock();
+    set_or_reset(title.as_ref())
+}
+
+/// Set `title`, or put back the original if it's empty.
+#[cfg(feature = "std")]
+fn set_or_reset(title: &OsStr) -> Result<(), TitleError> {
+    let result = match title.is_empty() {
+        true => imp::reset_title(),
+        false => imp::set_title(title),
+    };
+    record(title, result)
+}
+
+/// Remember `title` if `result` says it was set, even if only in part, count
+/// it for the `metrics` feature, and pass `result` on.
+///
+/// Truncated titles were still set, and so were most that failed with an OS
+/// error, such as a Linux title blocked from the process name but written
+/// over argv.  A NUL or a platform without titles means nothing changed, so
+/// the title before is still the one showing.
+#[cfg(feature = "std")]
+fn record(title: &OsStr, result: Result<(), TitleError>) -> Result<(), TitleError> {
+    if was_set(&result) {
+        remember(title);
+    }
+
+    #[cfg(feature = "metrics")]
+    count_title(&result);
+
+    result
+}
+
+/// Whether a title was set, going by the `result` of setting it, as record()
+/// judges it.
+#[cfg(feature = "std")]
+fn was_set(result: &Result<(), TitleError>) -> bool {
+    !matches!(
+        result,
+        Err(TitleError::InteriorNul) | Err(TitleError::Unsupported)
+    )
+}
+
+/// Note `title` as the one last set, for verify_title(), an empty one meaning
+/// the original.  This reuses the buffer, so it rarely allocates.
+#[cfg(feature = "std")]
+fn remember(title: &OsStr) {
+    if !title.is_empty() {
+        FIRST_TITLE.get_or_init(Instant::now);
+    }
+
+    let mut last = LAST_TITLE.lock().unwrap_or_else(|e| e.into_inner());
+    last.clear();
+    last.push(title);
+}
+
+// When a title was first set, for since_first_title().
+#[cfg(feature = "std")]
+static FIRST_TITLE: std::sync::OnceLock<Instant> = std::sync::OnceLock::new();
+
+/// How long it's been since a title was first set, or `None` if one hasn't
+/// been yet.
+///
+/// This saves passing a start time around just to show an uptime:
+///
+/// ```
+/// proctitle::set_title("myapp: starting");
+///
+/// let up = proctitle::since_first_title().unwrap_or_default().as_secs();
+/// proctitle::set_title(format!("myapp: up {:02}:{:02}:{:02}", up / 3600, up / 60 % 60, up % 60));
+/// ```
+///
+/// Resetting the title doesn't restart the clock.
+#[cfg(feature = "std")]
+pub fn since_first_title() -> Option<Duration> {
+    FIRST_TITLE.get().map(Instant::elapsed)
+}
+
+/// Whether the title last set is the one that can be read back with
+/// [`get_title`], for health checks and integration tests.
+///
+/// This is `false` if the title was truncated, or changed by something other
+/// than this crate, and `None` if titles can't be read back, or none has been
+/// set since the last [`reset_title`].  Titles set with
+/// [`set_title_signal_safe`] aren't taken into account, and nor are those
+/// rejected outright, such as for containing a NUL, which leave the title
+/// before them in place.  With [`bsd_prefix`] on, the title is expected to be
+/// read back with the prefix.
+#[cfg(feature = "std")]
+pub fn verify_title() -> Option<bool> {
+    let _lock = title_lock();
+    let last = LAST_TITLE.lock().unwrap_or_else(|e| e.into_inner());
+    if last.is_empty() {
+        return None;
+    }
+
+    #[cfg(any(
+        target_os = "freebsd",
+        target_os = "dragonfly",
+        target_os = "netbsd",
+        target_os = "openbsd",
+        target_os = "bitrig",
+        target_os = "linux",
+        target_os = "android",
+    ))]
+    let last = imp::rendered(&last);
+
+    imp::get_title().map(|title| title == *last)
+}
+
+/// Count a title change, for the `metrics` feature.
+#[cfg(feature = "metrics")]
+fn count_title(result: &Result<(), TitleError>) {
+    let result = match result {
+        Ok(()) => "ok",
+        Err(TitleError::Truncated { .. }) => "truncated",
+        Err(_) => "error",
+    };
+    metrics::counter!("proctitle.set_title", "result" => result).increment(1);
+}
+
+/// Which of the Linux title mechanisms to use.
+#[cfg(all(feature = "std", any(target_os = "linux", target_os = "android")))]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LinuxTitleMode {
+    /// Just the 15-byte name set by `prctl(PR_SET_NAME)`, shown by `top` and
+    /// `ps -o comm`.  This never moves the environment.
+    Comm,
+    /// Just the full title written over argv, shown by `ps` and
+    /// `/proc/self/cmdline`.
+    Argv,
+    /// Both, which is the default.
+    Both,
+}
+
+/// Set a process title on Linux using only the mechanisms given by `mode`.
+#[cfg(all(feature = "std", any(target_os = "linux", target_os = "android")))]
+pub fn set_title_with_mode<T: AsRef<OsStr>>(mode: LinuxTitleMode, title: T) {
+    if !is_enabled() {
+        return;
+    }
+
+    let _lock = title_lock();
+    let title = title.as_ref();
+    let result = match title.is_empty() {
+        true => imp::reset_title(),
+        false => imp::set_title_with_mode(mode, title),
+    };
+    let _ = record(title, result);
+}
+
+/// Choose the mechanisms used by [`set_title()`] and friends on Linux, by
+/// default [`LinuxTitleMode::Both`].
+#[cfg(all(feature = "std", any(target_os = "linux", target_os = "android")))]
+pub fn set_default_linux_mode(mode: LinuxTitleMode) {
+    imp::set_default_mode(mode);
+}
+
+/// Serialise changes to the process title.
+#[cfg(feature = "std")]
+fn title_lock() -> std::sync::MutexGuard<'static, ()> {
+    TITLE_LOCK.lock().unwrap_or_else(|e| e.into_inner())
+}
+
+/// Set a process title, returning the one it replaced, so it can be put back.
+///
+/// That's the title last set through this crate, as it was asked for, rather
+/// than as [`get_title`] reads it back, with any prefix or truncation.  It's
+/// `None` if the process still has its original title, which [`reset_title`]
+/// puts back, or if titles are silenced by [`set_enabled`].  Unlike calling
+/// [`get_title`] and then [`set_title()`], no other change to the title can
+/// sneak in between.
+///
+/// ```
+/// let previous = proctitle::replace_title("compacting");
+/// // ...
+/// match previous {
+///     Some(title) => proctitle::set_title(title),
+///     None => proctitle::reset_title(),
+/// }
+/// ```
+#[cfg(feature = "std")]
+pub fn replace_title<T: AsRef<OsStr>>(title: T) -> Option<OsString> {
+    swap_title(title.as_ref()).flatten()
+}
+
+/// Set a process title, as [`set_title()`], but optimised for frequent updates.
+///
+/// On FreeBSD this uses [`setproctitle_fast(3)`], which avoids a system call
+/// on every update.  Elsewhere it's the same as [`set_title()`].
+///
+/// [`setproctitle_fast(3)`]: https://www.freebsd.org/cgi/man.cgi?query=setproctitle_fast&sektion=3
+#[cfg(feature = "std")]
+pub fn set_title_fast<T: AsRef<OsStr>>(title: T) {
+    #[cfg(target_os = "freebsd")]
+    if is_enabled() {
+        let _lock = title_lock();
+        let title = title.as_ref();
+        let result = match title.is_empty() {
+            true => imp::reset_title(),
+            false => imp::set_title_fast(title),
+        };
+        let _ = record(title, result);
+    }
+
+    #[cfg(not(target_os = "freebsd"))]
+    set_title(title);
+}
+
+/// Set a process title made of `parts` joined by `sep`.
+///
+/// ```
+/// proctitle::set_title_segments(": ", ["myapp", "worker 3", "idle"]);
+/// ```
+///
+/// No parts makes for an empty title, which puts back the original.
+#[cfg(feature = "std")]
+pub fn set_title_segments<I, S>(sep: &str, parts: I)
+where
+    I: IntoIterator<Item = S>,
+    S: AsRef<OsStr>,
+{
+    let mut title = OsString::new();
+    for (i, part) in parts.into_iter().enumerate() {
+        if i > 0 {
+            title.push(sep);
+        }
+        title.push(part);
+    }
+
+    set_title(title);
+}
+
+/// Restore the process title to what it was before any of these functions
+/// changed it, typically the original command line.
+///
+/// This is also what setting an empty title does.
+///
+/// Where titles overwrite argv, as on Linux and macOS, a forked child that
+/// changes its title counts the one it was forked with as its original.
+#[cfg(feature = "std")]
+pub fn reset_title() {
+    let _lock = title_lock();
+    let _ = set_or_reset(OsStr::new(""));
+}
+
+/// Set a process title from within a signal handler.
+///
+/// This only does what's async-signal-safe: overwriting the original process
+/// arguments in place, and on Linux renaming the process.  It doesn't allocate
+/// or take locks, so:
+///
+/// * The title must be built beforehand, such as in a `static`.
+/// * Some other title function, such as [`set_title()`] or [`max_title_len`],
+///   must have been called first to find where the arguments are.  Until then
+///   Linux only sets the 15-byte name, and macOS, Solaris and illumos fail.
+/// * Titles are truncated to the space found so far, without moving the
+///   environment out of the way as [`set_title()`] might.
+/// * A title set concurrently from another thread may be garbled.
+///
+/// BSD's `setproctitle()` isn't async-signal-safe, so this returns
+/// [`TitleError::Unsupported`] there, as it does on Windows.
+#[cfg(feature = "std")]
+pub fn set_title_signal_safe(title: &CStr) -> Result<(), TitleError> {
+    if !is_enabled() {
+        return Ok(());
+    }
+
+    imp::set_title_signal_safe(title)
+}
+
+/// Set a process title from a C string, as [`set_title()`].
+///
+/// This is all that's available without the default `std` feature, for
+/// `#![no_std]` programs linked against libc.  There it only renames the
+/// process with `prctl(PR_SET_NAME)` on Linux and Android, which only does so
+/// when called from the main thread, or calls `setproctitle()` on the BSDs.
+/// Elsewhere it does nothing.
+pub fn set_title_cstr(title: &core::ffi::CStr) {
+    #[cfg(all(feature = "std", unix))]
+    set_title_bytes(title.to_bytes());
+
+    #[cfg(all(feature = "std", not(unix)))]
+    set_title(&*title.to_string_lossy());
+
+    #[cfg(not(feature = "std"))]
+    if is_enabled() {
+        imp::set_title_cstr(title);
+    }
+}
+
+/// Turn title changes on or off at runtime, such as from a configuration
+/// reload.  They're on by default.
+///
+/// While off, [`set_title()`], [`set_thread_title`] and the like return without
+/// doing anything, leaving whatever title was last set.  [`reset_title`] still
+/// works, to put the original back.
+pub fn set_enabled(enabled: bool) {
+    ENABLED.store(enabled, Ordering::Relaxed);
+}
+
+/// Whether title changes are on, as chosen by [`set_enabled`].
+pub fn is_enabled() -> bool {
+    ENABLED.load(Ordering::Relaxed)
+}
+
+/// Set a process title from a C string with as little work as possible, for
+/// titles updated many times a second.
+///
+/// On the BSDs the title goes to `setproctitle()` as it is, rather than first
+/// being copied to add a NUL.  It still takes the lock every title change
+/// does, and is copied once to be remembered for [`replace_title`] and
+/// [`verify_title`], and on OpenBSD again for [`get_title`].  Elsewhere this is
+/// the same as [`set_title_bytes`], which is already as cheap as it gets.
+#[cfg(all(feature = "std", unix))]
+pub fn set_title_raw(title: &CStr) {
+    if !is_enabled() {
+        return;
+    }
+
+    let _lock = title_lock();
+
+    #[cfg(any(
+        target_os = "freebsd",
+        target_os = "dragonfly",
+        target_os = "netbsd",
+        target_os = "openbsd",
+        target_os = "bitrig",
+    ))]
+    {
+        use std::os::unix::ffi::OsStrExt;
+        let result = match title.to_bytes() {
+            [] => imp::reset_title(),
+            _ => imp::set_title_raw(title),
+        };
+        let _ = record(OsStr::from_bytes(title.to_bytes()), result);
+    }
+
+    #[cfg(not(any(
+        target_os = "freebsd",
+        target_os = "dragonfly",
+        target_os = "netbsd",
+        target_os = "openbsd",
+        target_os = "bitrig",
+    )))]
+    {
+        use std::os::unix::ffi::OsStrExt;
+        let _ = set_or_reset(OsStr::from_bytes(title.to_bytes()));
+    }
+}
+
+/// Set a process title from NUL-terminated UTF-16 with as little work as
+/// possible, for titles updated many times a second.
+///
+/// The title goes to Windows as it is, rather than being re-encoded and cut
+/// down to [`MAX_CONSOLE_TITLE`] first, and everything after its first NUL is
+/// ignored.  Titles without a trailing NUL aren't set at all.  It still takes
+/// the lock every title change does, and is decoded once to be remembered for
+/// [`replace_title`] and [`verify_title`].
+#[cfg(all(feature = "std", windows))]
+pub fn set_title_raw_wide(title: &[u16]) {
+    use std::os::windows::ffi::OsStringExt;
+
+    if !is_enabled() || title.last() != Some(&0) {
+        return;
+    }
+
+    let _lock = title_lock();
+    let result = match title {
+        [0, ..] => imp::reset_title(),
+        _ => imp::set_title_raw_wide(title),
+    };
+
+    let len = title.iter().position(|&c| c == 0).unwrap_or(0);
+    let _ = record(&OsString::from_wide(&title[..len]), result);
+}
+
+/// Set different titles for the Windows console and for the named event
+/// object, as opposed to [`set_title()`], which uses the same one for both.
+///
+/// A short console title suits a terminal tab, while the event's name, seen
+/// in Process Explorer, has room for more detail:
+///
+/// ```
+/// proctitle::set_windows_titles("compactor", "compactor: merging shard 7 of 12");
+/// ```
+///
+/// [`get_title`] returns the console title, or the event title when there's
+/// no console.  An empty console title puts back the original, while an empty
+/// event title leaves no event at all, and both empty is the same as
+/// [`reset_title`].  The event title is ignored without the
+/// `windows-event-handle` feature.
+#[cfg(all(feature = "std", windows))]
+pub fn set_windows_titles<C: AsRef<OsStr>, E: AsRef<OsStr>>(console: C, event: E) {
+    if !is_enabled() {
+        return;
+    }
+
+    let _lock = title_lock();
+    let result = imp::set_titles(console.as_ref(), event.as_ref());
+    let _ = record(console.as_ref(), result);
+}
+
+/// Get the current process title, if possible.
+///
+/// On Linux this is the full title from the command line, as `ps` shows it,
+/// unless the last title was set with [`LinuxTitleMode::Comm`], in which case
+/// it's the 15-byte process name.
+#[cfg(feature = "std")]
+pub fn get_title() -> Option<OsString> {
+    imp::get_title()
+}
+
+/// A handle that formats as the live process title, for logging.
+///
+/// ```
+/// println!("title now {}", proctitle::CurrentTitle);
+/// ```
+///
+/// Formats as `<unsupported>` on platforms without titles, or `<unknown>` if
+/// the title can't be read back.  Its `Debug` output describes the platform.
+#[cfg(feature = "std")]
+#[derive(Clone, Copy, Default)]
+pub struct CurrentTitle;
+
+#[cfg(feature = "std")]
+impl fmt::Display for CurrentTitle {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match get_title() {
+            Some(title) => f.write_str(&title.to_string_lossy()),
+            None if !is_supported() => f.write_str("<unsupported>"),
+            None => f.write_str("<unknown>"),
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl fmt::Debug for CurrentTitle {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("CurrentTitle")
+            .field("platform", &std::env::consts::OS)
+            .field("supported", &is_supported())
+            .finish()
+    }
+}
+
+/// Whether this platform has any means of setting a process title.
+///
+/// If not, [`set_title()`] and friends do nothing, so there's little point
+/// building an elaborate title to pass them.
+pub const fn is_supported() -> bool {
+    imp::SUPPORTED
+}
+
+// Landing in a no-op backend is otherwise silent, as it was for macOS until it
+// got one of its own.
+#[cfg(all(feature = "require-backend", not(feature = "disabled")))]
+const _: () = assert!(
+    imp::SUPPORTED,
+    "the require-backend feature is on, but proctitle can't set titles on this target"
+);
+
+/// Whether the process is attached to a console, so [`set_title()`] can set its
+/// title.
+///
+/// Without one, such as when running as a service, titles are only visible
+/// through the event object described in the README.
+#[cfg(all(feature = "std", windows))]
+pub fn has_console() -> bool {
+    imp::has_console()
+}
+
+/// The name of the event object currently exposing the title on Windows, if
+/// there is one, including any prefix from [`set_title_prefix`].
+///
+/// Another process can open it with `OpenEventW()`, such as to signal this
+/// one.  The name changes along with the title.
+///
+/// Requires the `windows-event-handle` feature.
+#[cfg(all(feature = "std", windows, feature = "windows-event-handle"))]
+pub fn event_handle_name() -> Option<OsString> {
+    imp::event_name()
+}
+
+/// Release anything held on to for the sake of the title, ahead of exiting.
+///
+/// Statics are never dropped, so otherwise this is left to the OS as the
+/// process exits.  On Windows this closes the event object exposing the title,
+/// which disappears once no other process has it open; the console title is
+/// left as it is.  The next title set creates a new one.  Other platforms have
+/// nothing to release.
+#[cfg(feature = "std")]
+pub fn cleanup() {
+    #[cfg(all(windows, feature = "windows-event-handle"))]
+    imp::close_event();
+}
+
+/// The handle of the event object currently exposing the title on Windows, if
+/// there is one.
+///
+/// It's closed when the title next changes, or on [`reset_title`], so use
+/// `DuplicateHandle()` to keep hold of it for longer.
+///
+/// Requires the `windows-event-handle` feature.
+#[cfg(all(feature = "std", windows, feature = "windows-event-handle"))]
+pub fn raw_event_handle() -> Option<std::os::windows::io::RawHandle> {
+    imp::raw_event_handle()
+}
+
+/// Find the titles of processes whose event objects are named starting with
+/// `prefix`, as given to their [`set_title_prefix`], with the prefix removed.
+///
+/// This lets a supervisor discover its workers' titles without a console of
+/// its own, as long as they share a prefix:
+///
+/// ```no_run
+/// for title in proctitle::find_titles("myapp-worker:").unwrap() {
+///     println!("{}", title.to_string_lossy());
+/// }
+/// ```
+///
+/// Prefixes starting with `Global\` search the global namespace, and others
+/// that of the current session.  Errors listing the namespace are returned,
+/// such as [`io::ErrorKind::PermissionDenied`] where it can't be read.
+///
+/// Requires the `windows-event-handle` feature.
+#[cfg(all(feature = "std", windows, feature = "windows-event-handle"))]
+pub fn find_titles<T: AsRef<OsStr>>(prefix: T) -> io::Result<Vec<OsString>> {
+    imp::find_titles(prefix.as_ref())
+}
+
+/// How titles are set on this platform.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+#[non_exhaustive]
+pub enum Mechanism {
+    /// The BSDs' `setproctitle()`.
+    Setproctitle,
+    /// Linux's `prctl(PR_SET_NAME)`, limited to 15 bytes, as argv couldn't be
+    /// found, or `/proc/self/maps` says it isn't writable, as under some
+    /// hardened kernels and sanitizers.
+    Prctl,
+    /// Overwriting the original argv strings, which on Linux is done alongside
+    /// `prctl(PR_SET_NAME)`.
+    ArgvOverwrite,
+    /// The Windows console title, and a named event object.
+    ConsoleAndEvent,
+    /// The Windows console title alone, without the `windows-event-handle`
+    /// feature.
+    Console,
+    /// Renaming the main thread, on Haiku and QNX.
+    RenameThread,
+    /// Keeping the title in memory, with the `mock` feature.
+    Mock,
+    /// Nothing at all.
+    Noop,
+}
+
+impl Mechanism {
+    /// A sentence fragment saying where titles end up and how long they can
+    /// be, for diagnostic output.
+    ///
+    /// ```
+    /// let mechanism = proctitle::platform_info().mechanism;
+    /// println!("process titles: {}", mechanism.describe());
+    /// ```
+    pub fn describe(&self) -> &'static str {
+        match self {
+            Mechanism::Setproctitle => "setproctitle(), as shown by ps, limited to 2047 bytes",
+            Mechanism::Prctl => {
+                "prctl(PR_SET_NAME) only, as shown by top and ps -o comm, limited to 15 \
+                 bytes, as the command line couldn't be found or isn't writable"
+            }
+            Mechanism::ArgvOverwrite if cfg!(any(target_os = "linux", target_os = "android")) => {
+                "the original command line overwritten, as shown by ps, limited to the \
+                 space of the arguments and environment, and prctl(PR_SET_NAME), as shown \
+                 by top, limited to 15 bytes"
+            }
+            Mechanism::ArgvOverwrite => {
+                "the original command line overwritten, as shown by ps, limited to the \
+                 space of the arguments"
+            }
+            Mechanism::ConsoleAndEvent => {
+                "the console title, limited to 1024 characters, and a named event object, \
+                 as shown by Process Explorer, limited to 259"
+            }
+            Mechanism::Console => "the console title, limited to 1024 characters",
+            Mechanism::RenameThread if cfg!(target_os = "haiku") => {
+                "the main thread's name, as shown by ProcessController, limited to 31 bytes"
+            }
+            Mechanism::RenameThread if cfg!(target_os = "nto") => {
+                "the main thread's name, as shown by pidin, limited to 99 bytes"
+            }
+            Mechanism::RenameThread => "the main thread's name",
+            Mechanism::Mock => "kept in memory for testing, and only seen by get_title()",
+            Mechanism::Noop => "nothing, as titles can't be set here",
+        }
+    }
+}
+
+/// A description of how process titles work on this platform, for diagnostics.
+///
+/// Returned by [`platform_info`].
+#[cfg(feature = "std")]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct PlatformInfo {
+    /// The operating system, as in [`std::env::consts::OS`].
+    pub os: &'static str,
+    /// How titles are set.
+    pub mechanism: Mechanism,
+    /// The longest title that fits, as [`max_title_len`].
+    pub max_len: Option<usize>,
+}
+
+/// Describe how process titles work on this platform.
+///
+/// ```
+/// let info = proctitle::platform_info();
+/// println!("process titles via {:?} on {}", info.mechanism, info.os);
+/// ```
+#[cfg(feature = "std")]
+pub fn platform_info() -> PlatformInfo {
+    PlatformInfo {
+        os: std::env::consts::OS,
+        mechanism: imp::mechanism(),
+        max_len: max_title_len(),
+    }
+}
+
+/// The current title and how it was set, for structured logs and health
+/// checks.
+///
+/// Returned by [`snapshot`], and serialized with serde as a struct.
+#[cfg(feature = "serde")]
+#[derive(Debug, Clone, PartialEq, Eq, serde::Serialize)]
+#[non_exhaustive]
+pub struct TitleState {
+    /// The title, as [`get_title`], with anything that isn't valid Unicode
+    /// replaced with `�`.
+    pub title: Option<String>,
+    /// How titles are set.
+    pub mechanism: Mechanism,
+    /// Whether titles can be set, as [`is_supported`].
+    pub supported: bool,
+}
+
+/// Take a [`TitleState`] snapshot of the current title.
+///
+/// ```
+/// let state = proctitle::snapshot();
+/// println!("{}", serde_json::to_string(&state).unwrap());
+/// ```
+///
+/// Requires the `serde` feature.
+#[cfg(feature = "serde")]
+pub fn snapshot() -> TitleState {
+    TitleState {
+        title: get_title().map(|title| title.to_string_lossy().into_owned()),
+        mechanism: imp::mechanism(),
+        supported: is_supported(),
+    }
+}
+
+/// Choose whether titles start with the program name, as in
+/// `progname: title`, the classic BSD `setproctitle()` style.
+///
+/// On Linux and Android this applies to the full title seen in `ps` and
+/// `/proc/self/cmdline`, with the name taken from the original `argv[0]`, so
+/// `pgrep -f 'progname:'` works as it would on the BSDs.  The 15-byte process
+/// name is left without it, as there's little enough room already.
+///
+/// [`get_title`] reads the prefix back along with the title, but
+/// [`replace_title`] and the guard from [`set_title_scoped`] return and
+/// restore titles as they were asked for, so it's never added twice.
+///
+/// This is off by default, and takes effect from the next title set.  It has
+/// no effect on other platforms.
+#[cfg(feature = "std")]
+pub fn bsd_prefix(enabled: bool) {
+    #[cfg(any(
+        target_os = "freebsd",
+        target_os = "dragonfly",
+        target_os = "netbsd",
+        target_os = "openbsd",
+        target_os = "bitrig",
+        target_os = "linux",
+        target_os = "android",
+    ))]
+    imp::set_prefix_enabled(enabled);
+
+    #[cfg(not(any(
+        target_os = "freebsd",
+        target_os = "dragonfly",
+        target_os = "netbsd",
+        target_os = "openbsd",
+        target_os = "bitrig",
+        target_os = "linux",
+        target_os = "android",
+    )))]
+    let _ = enabled;
+}
+
+/// Choose whether Windows titles are also written to stdout as an `ESC ] 0 ;`
+/// escape sequence, for terminals such as Windows Terminal whose tabs don't
+/// always follow the console title.
+///
+/// This is off by default, and when on, only applies while stdout is a console
+/// with virtual terminal processing enabled, never a file or pipe.  It has no
+/// effect on other platforms.
+#[cfg(feature = "std")]
+pub fn windows_vt_title(enabled: bool) {
+    #[cfg(windows)]
+    imp::set_vt_enabled(enabled);
+
+    #[cfg(not(windows))]
+    let _ = enabled;
+}
+
+/// Set a prefix for the name of the event object used to expose titles on
+/// Windows, such as `"myapp:"`, to tell it apart from those of other programs.
+///
+/// This takes effect from the next [`set_title()`] call.  Note that `\` isn't
+/// allowed in object names, except in a leading `Global\` or `Local\`.
+///
+/// Other platforms ignore this.
+#[cfg(feature = "std")]
+pub fn set_title_prefix<T: AsRef<OsStr>>(prefix: T) {
+    #[cfg(all(windows, feature = "windows-event-handle"))]
+    imp::set_prefix(prefix.as_ref());
+
+    #[cfg(not(all(windows, feature = "windows-event-handle")))]
+    let _ = prefix;
+}
+
+/// The longest title that can be set without being truncated, if known.
+///
+/// This is measured in bytes, except on Windows, where it's in UTF-16 code
+/// units.  `None` means titles aren't supported at all.
+#[cfg(feature = "std")]
+pub fn max_title_len() -> Option<usize> {
+    imp::max_title_len()
+}
+
+/// Set the name of the calling thread, if possible.
+///
+/// Where [`set_title()`] names the process as a whole, this only affects the
+/// current thread, as might be seen in `top -H` or a debugger.  A thread pool
+/// might use it to label its workers.
+#[cfg(feature = "std")]
+pub fn set_thread_title<T: AsRef<OsStr>>(title: T) {
+    if !is_enabled() {
+        return;
+    }
+
+    let _ = imp::set_thread_title(title.as_ref());
+}
+
+/// Set a title everywhere this platform can show one, for the most visibility
+/// in the most tools.
+///
+/// That's the process title, as [`set_title()`], the calling thread's name, as
+/// [`set_thread_title`], and on Unix the terminal title, as
+/// [`set_terminal_title`].  Each is best-effort, so whichever can't be set
+/// doesn't stop the rest.  An empty title resets the process title and leaves
+/// the thread name and terminal title alone, rather than blanking them.
+///
+/// ```
+/// proctitle::set_all_titles("myapp: indexing");
+/// ```
+#[cfg(feature = "std")]
+pub fn set_all_titles<T: AsRef<OsStr>>(title: T) {
+    let title = title.as_ref();
+    set_title(title);
+    if title.is_empty() {
+        return;
+    }
+
+    set_thread_title(title);
+    #[cfg(unix)]
+    set_terminal_title(title);
+}
+
+/// Get the name of the calling thread, as set by [`set_thread_title`] or
+/// inherited from the thread that created it.
+///
+/// Only Linux and Android support this, reading back the 15 bytes the kernel
+/// keeps.  Elsewhere this returns `None`.
+#[cfg(feature = "std")]
+pub fn get_thread_title() -> Option<OsString> {
+    imp::get_thread_title()
+}
+
+/// Restores the previous process title when dropped.
+///
+/// Returned by [`set_title_scoped`].
+#[cfg(feature = "std")]
+#[derive(Debug)]
+#[must_use = "the previous title is restored as soon as the guard is dropped"]
+pub struct TitleGuard {
+    // The title last asked for beforehand, None for the original, or nothing
+    // at all if titles were silenced and there's nothing to undo.
+    previous: Option<Option<OsString>>,
+}
+
+#[cfg(feature = "std")]
+impl Drop for TitleGuard {
+    fn drop(&mut self) {
+        match self.previous.take() {
+            Some(Some(title)) => set_title(title),
+            Some(None) => reset_title(),
+            None => (),
+        }
+    }
+}
+
+/// Set a process title until the returned guard is dropped, at which point the
+/// previous title is restored.
+///
+/// That's the title last set through this crate, or the original if there
+/// wasn't one, rather than whatever [`get_title`] would have read back, so
+/// the original command line isn't written back as a single title.
+#[cfg(feature = "std")]
+pub fn set_title_scoped<T: AsRef<OsStr>>(title: T) -> TitleGuard {
+    TitleGuard {
+        previous: swap_title(title.as_ref()),
+    }
+}
+
+/// Set a process title, returning the title last asked for, `Some(None)` if
+/// that was the original, or `None` if titles are silenced.
+#[cfg(feature = "std")]
+fn swap_title(title: &OsStr) -> Option<Option<OsString>> {
+    if !is_enabled() {
+        return None;
+    }
+
+    let _lock = title_lock();
+    let previous = LAST_TITLE.lock().unwrap_or_else(|e| e.into_inner()).clone();
+    let _ = set_or_reset(title);
+    Some(Some(previous).filter(|title| !title.is_empty()))
+}
+
+/// Run `f` under a temporary process title, restoring the previous title
+/// afterwards, even if `f` panics.
+///
+/// ```
+/// # fn do_compaction() {}
+/// proctitle::with_title("compacting", || do_compaction());
+/// ```
+#[cfg(feature = "std")]
+pub fn with_title<T, F, R>(title: T, f: F) -> R
+where
+    T: AsRef<OsStr>,
+    F: FnOnce() -> R,
+{
+    let _guard = set_title_scoped(title);
+    f()
+}
+
+/// Set a process title, saving it on a stack so a later [`pop_title`] can
+/// return to the title beneath it.
+#[cfg(feature = "std")]
+pub fn push_title<T: AsRef<OsStr>>(title: T) {
+    let mut stack = TITLE_STACK.lock().expect("title stack lock");
+    set_title(&title);
+    stack.push(title.as_ref().to_owned());
+}
+
+/// Remove the most recently pushed title, restoring the one beneath it, or
+/// the original title if there isn't one.
+///
+/// Does nothing if the stack is empty.
+#[cfg(feature = "std")]
+pub fn pop_title() {
+    let mut stack = TITLE_STACK.lock().expect("title stack lock");
+    if stack.pop().is_some() {
+        set_title(stack.last().map(OsString::as_os_str).unwrap_or_default());
+    }
+}
+
+/// The name of the running executable, if it can be found.
+#[cfg(feature = "std")]
+fn default_base_title() -> OsString {
+    std::env::current_exe()
+        .ok()
+        .and_then(|exe| exe.file_name().map(OsStr::to_owned))
+        .unwrap_or_default()
+}
+
+/// Set the base used by [`set_title_suffix`], by default the name of the
+/// executable.
+#[cfg(feature = "std")]
+pub fn set_base_title<T: AsRef<OsStr>>(base: T) {
+    *BASE_TITLE.lock().expect("base title lock") = base.as_ref().to_owned();
+}
+
+/// Use the program name from `argv[0]` as the base for [`set_title_suffix`].
+///
+/// Unlike the default, taken from the executable's path, this gives the name
+/// the program was run as, such as that of a symlink.  Only the first call
+/// does anything, so it's cheap to call repeatedly.
+#[cfg(feature = "std")]
+pub fn init() {
+    static INIT: std::sync::Once = std::sync::Once::new();
+    INIT.call_once(|| {
+        if let Some(name) = std::env::args_os()
+            .next()
+            .and_then(|arg| Path::new(&arg).file_name().map(OsStr::to_owned))
+        {
+            set_base_title(name);
+        }
+    });
+}
+
+/// Set a process title of the form `base: suffix`, where the base is set with
+/// [`set_base_title`], in the style of PostgreSQL's worker processes.
+///
+/// ```
+/// proctitle::set_base_title("postgres");
+/// proctitle::set_title_suffix("autovacuum worker");
+/// ```
+///
+/// The separator is omitted if the base is empty.
+#[cfg(feature = "std")]
+pub fn set_title_suffix<T: AsRef<OsStr>>(suffix: T) {
+    let base = BASE_TITLE.lock().expect("base title lock");
+    if base.is_empty() {
+        set_title(suffix);
+    } else {
+        set_title_segments(": ", [base.as_os_str(), suffix.as_ref()]);
+    }
+}
+
+#[cfg(all(feature = "std", test))]
+lazy_static! {
+    static ref TEST_LOCK: Mutex<()> = Mutex::new(());
+}
+
+/// Serialise tests which set the process-wide title.
+#[cfg(all(feature = "std", test))]
+fn test_lock() -> std::sync::MutexGuard<'static, ()> {
+    TEST_LOCK.lock().unwrap_or_else(|e| e.into_inner())
+}
+
+#[cfg(feature = "std")]
+#[test]
+fn set_title_is_at_least_callable() {
+    let _lock = test_lock();
+    set_title("What was it like being a hamster?");
+    set_title(String::from("It was better than being a chicken."));
+    set_title(std::ffi::OsString::from(
+        "Have you seen the size of an egg?",
+    ));
+}
+
+#[cfg(all(
+    feature = "metrics",
+    not(any(feature = "disabled", feature = "mock")),
+    any(target_os = "linux", target_os = "android")
+))]
+#[test]
+fn set_title_counts_titles() {
+    use metrics::{Counter, Gauge, Histogram, Key, KeyName, Metadata, SharedString, Unit};
+
+    #[derive(Default)]
+    struct Recorder(Mutex<Vec<String>>);
+
+    impl metrics::Recorder for Recorder {
+        fn describe_counter(&self, _: KeyName, _: Option<Unit>, _: SharedString) {}
+        fn describe_gauge(&self, _: KeyName, _: Option<Unit>, _: SharedString) {}
+        fn describe_histogram(&self, _: KeyName, _: Option<Unit>, _: SharedString) {}
+
+        fn register_counter(&self, key: &Key, _: &Metadata<'_>) -> Counter {
+            let labels: Vec<_> = key.labels().map(|l| l.value().to_owned()).collect();
+            let mut counted = self.0.lock().unwrap();
+            counted.push(format!("{} {}", key.name(), labels.join(",")));
+            Counter::noop()
+        }
+
+        fn register_gauge(&self, _: &Key, _: &Metadata<'_>) -> Gauge {
+            Gauge::noop()
+        }
+
+        fn register_histogram(&self, _: &Key, _: &Metadata<'_>) -> Histogram {
+            Histogram::noop()
+        }
+    }
+
+    let _lock = test_lock();
+    let recorder = Recorder::default();
+    metrics::with_local_recorder(&recorder, || {
+        set_title("counted");
+        set_title("x".repeat(64 * 1024));
+        set_title("nul\0");
+        set_title_with_mode(LinuxTitleMode::Comm, "comm only");
+        reset_title();
+    });
+
+    assert_eq!(
+        *recorder.0.lock().unwrap(),
+        [
+            "proctitle.set_title ok",
+            "proctitle.set_title truncated",
+            "proctitle.set_title error",
+            "proctitle.set_title ok",
+            "proctitle.set_title ok",
+        ]
+    );
+}
+
+#[cfg(all(
+    feature = "log",
+    not(any(feature = "disabled", feature = "mock")),
+    any(target_os = "linux", target_os = "android")
+))]
+#[test]
+fn log_and_title_sets_title() {
+    let _lock = test_lock();
+    log_and_title!(log::Level::Info, "compacting {} segments", 42);
+    assert_eq!(get_title().unwrap(), "compacting 42 segments");
+}
+
+#[cfg(all(
+    feature = "std",
+    not(any(feature = "disabled", feature = "mock")),
+    any(target_os = "linux", target_os = "android")
+))]
+#[test]
+fn set_title_macro_formats_title() {
+    let _lock = test_lock();
+    set_title!("worker {}: {} items", 3, 42);
+    assert_eq!(get_title().unwrap(), "worker 3: 42 items");
+    set_title!(String::from("worker {}: idle"));
+    assert_eq!(get_title().unwrap(), "worker {}: idle");
+
+    let n = 7;
+    set_title!("job {n}");
+    assert_eq!(get_title().unwrap(), "job 7");
+    set_title!("{{literal}}");
+    assert_eq!(get_title().unwrap(), "{literal}");
+}
+
+#[cfg(all(
+    feature = "std",
+    not(any(feature = "disabled", feature = "mock")),
+    any(target_os = "linux", target_os = "android")
+))]
+#[test]
+fn set_title_fmt_keeps_percent_signs() {
+    let _lock = test_lock();
+    set_title_fmt(format_args!("{}% done", 100));
+    assert_eq!(get_title().unwrap(), "100% done");
+    set_title_fmt(format_args!("rate: 5%/s"));
+    assert_eq!(get_title().unwrap(), "rate: 5%/s");
+    reset_title();
+}
+
+#[cfg(all(
+    feature = "std",
+    not(any(feature = "disabled", feature = "mock")),
+    any(target_os = "linux", target_os = "android")
+))]
+#[test]
+fn set_title_here_names_function() {
+    let _lock = test_lock();
+    set_title_here!();
+    assert_eq!(
+        get_title().unwrap(),
+        "proctitle::set_title_here_names_function"
+    );
+    set_title_here!("step {}", 2);
+    assert_eq!(
+        get_title().unwrap(),
+        "proctitle::set_title_here_names_function: step 2"
+    );
+    reset_title();
+}
+
+#[cfg(all(
+    feature = "std",
+    not(any(feature = "disabled", feature = "mock")),
+    any(target_os = "linux", target_os = "android")
+))]
+#[test]
+fn set_title_bytes_sets_raw_title() {
+    use std::os::unix::ffi::OsStrExt;
+
+    let _lock = test_lock();
+    set_title_bytes(b"host-\xff\xfe");
+    assert_eq!(get_title().unwrap(), OsStr::from_bytes(b"host-\xff\xfe"));
+    set_title_bytes(b"host\0name");
+    assert_eq!(get_title().unwrap(), OsStr::from_bytes(b"host-\xff\xfe"));
+}
+
+#[cfg(all(
+    feature = "std",
+    not(any(feature = "disabled", feature = "mock")),
+    any(target_os = "linux", target_os = "android")
+))]
+#[test]
+fn set_title_once_keeps_first_title() {
+    let _lock = test_lock();
+    set_enabled(false);
+    set_title_once("while disabled");
+    set_enabled(true);
+    set_title_once("rejected\0");
+
+    set_title_once("chosen by main");
+    set_title_once("chosen by a dependency");
+    assert_eq!(get_title().unwrap(), "chosen by main");
+}
+
+#[cfg(all(
+    feature = "std",
+    not(any(feature = "disabled", feature = "mock")),
+    any(target_os = "linux", target_os = "android")
+))]
+#[test]
+fn empty_title_resets_title() {
+    let _lock = test_lock();
+    reset_title();
+    let original = get_title().unwrap();
+    let comm = std::fs::read("/proc/self/comm").unwrap();
+
+    set_title("about to be emptied");
+    set_title("");
+    assert_eq!(get_title().unwrap(), original);
+    assert_eq!(std::fs::read("/proc/self/comm").unwrap(), comm);
+}
+
+#[cfg(all(
+    feature = "std",
+    not(any(feature = "disabled", feature = "mock")),
+    any(target_os = "linux", target_os = "android")
+))]
+#[test]
+fn verify_title_compares_with_read_back() {
+    let _lock = test_lock();
+    reset_title();
+    assert_eq!(verify_title(), None);
+
+    set_title("verified");
+    assert_eq!(verify_title(), Some(true));
+
+    set_title("v".repeat(max_title_len().unwrap() + 1));
+    assert_eq!(verify_title(), Some(false));
+}
+
+#[cfg(all(
+    feature = "std",
+    not(any(feature = "disabled", feature = "mock")),
+    any(target_os = "linux", target_os = "android")
+))]
+#[test]
+fn verify_title_ignores_rejected_title() {
+    let _lock = test_lock();
+    set_title("verified");
+    assert!(set_title_checked("a\0b").is_err());
+    assert_eq!(get_title().unwrap(), "verified");
+    assert_eq!(verify_title(), Some(true));
+    reset_title();
+}
+
+#[cfg(all(
+    feature = "std",
+    not(any(feature = "disabled", feature = "mock")),
+    any(
+        target_os = "freebsd",
+        target_os = "dragonfly",
+        target_os = "netbsd",
+        target_os = "openbsd",
+        target_os = "bitrig",
+        target_os = "linux",
+        target_os = "android",
+    )
+))]
+#[test]
+fn verify_title_expects_bsd_prefix() {
+    let _lock = test_lock();
+    bsd_prefix(true);
+    set_title("verified");
+    let verified = verify_title();
+    #[cfg(any(target_os = "linux", target_os = "android"))]
+    let comm_verified = {
+        set_title_with_mode(LinuxTitleMode::Comm, "comm only");
+        verify_title()
+    };
+    bsd_prefix(false);
+
+    assert_eq!(verified, Some(true));
+    #[cfg(any(target_os = "linux", target_os = "android"))]
+    assert_eq!(comm_verified, Some(true));
+    reset_title();
+}
+
+#[cfg(feature = "std")]
+#[test]
+fn sanitize_replaces_control_characters() {
+    assert_eq!(
+        sanitize(OsStr::new("GET /\x1b[2J?q=\r\nHost: x\u{9b}31m")),
+        "GET /?[2J?q=??Host: x?31m"
+    );
+    assert_eq!(sanitize(OsStr::new("tab\there\0nul\x7f")), "tab?here?nul?");
+    assert_eq!(sanitize(OsStr::new("café ☕")), "café ☕");
+}
+
+#[cfg(all(feature = "std", not(feature = "disabled")))]
+#[test]
+fn since_first_title_counts_from_first_title() {
+    let _lock = test_lock();
+    set_title("ticking");
+    let first = since_first_title().unwrap();
+    reset_title();
+    std::thread::sleep(Duration::from_millis(10));
+    assert!(since_first_title().unwrap() >= first + Duration::from_millis(10));
+}
+
+#[cfg(all(
+    feature = "std",
+    any(unix, all(windows, not(any(feature = "disabled", feature = "mock"))))
+))]
+#[test]
+fn osc_title_replaces_control_characters() {
+    assert_eq!(
+        osc_title(OsStr::new("job\x07\x1b]0;x"), usize::MAX),
+        b"\x1b]0;job??]0;x\x07"
+    );
+}
+
+#[cfg(feature = "serde")]
+#[test]
+fn snapshot_serializes_title() {
+    let _lock = test_lock();
+    set_title("snapshot");
+
+    let state = serde_json::to_value(snapshot()).unwrap();
+    assert_eq!(state["mechanism"], format!("{:?}", imp::mechanism()));
+    assert_eq!(state["supported"], is_supported());
+    if is_supported() {
+        assert_eq!(state["title"], "snapshot");
+    }
+    reset_title();
+}
+
+#[cfg(all(feature = "std", unix))]
+#[test]
+fn osc_title_clamps_to_width() {
+    assert_eq!(osc_title(OsStr::new("héllo"), 2), b"\x1b]0;h\xc3\xa9\x07");
+    assert_eq!(osc_title(OsStr::new("hi"), 80), b"\x1b]0;hi\x07");
+}
+
+#[cfg(all(
+    feature = "std",
+    not(any(feature = "disabled", feature = "mock")),
+    any(target_os = "linux", target_os = "android")
+))]
+#[test]
+fn set_enabled_silences_titles() {
+    let _lock = test_lock();
+    set_title("before");
+    set_enabled(false);
+    set_title("while disabled");
+    let silenced = get_title();
+    set_enabled(true);
+    assert_eq!(silenced.unwrap(), "before");
+
+    set_title("after");
+    assert_eq!(get_title().unwrap(), "after");
+}
+
+#[cfg(all(
+    feature = "std",
+    not(any(feature = "disabled", feature = "mock")),
+    any(target_os = "linux", target_os = "android")
+))]
+#[test]
+fn set_all_titles_names_process_and_thread() {
+    let _lock = test_lock();
+    let thread = std::thread::spawn(|| {
+        set_all_titles("everywhere");
+        get_thread_title()
+    })
+    .join()
+    .unwrap();
+
+    assert_eq!(thread.unwrap(), "everywhere");
+    assert_eq!(get_title().unwrap(), "everywhere");
+    assert_eq!(std::fs::read("/proc/self/comm").unwrap(), b"everywhere\n");
+}
+
+#[cfg(all(
+    feature = "std",
+    not(any(feature = "disabled", feature = "mock")),
+    any(target_os = "linux", target_os = "android")
+))]
+#[test]
+fn set_title_with_pid_appends_pid() {
+    let _lock = test_lock();
+    set_title_with_pid("worker");
+    assert_eq!(
+        get_title().unwrap(),
+        OsString::from(format!("worker [{}]", std::process::id()))
+    );
+
+    reset_title();
+    let original = get_title().unwrap();
+    set_title("changed");
+    set_title_with_pid("");
+    assert_eq!(get_title().unwrap(), original);
+}
+
+#[cfg(all(
+    feature = "oom-score-adj",
+    not(any(feature = "disabled", feature = "mock")),
+    any(target_os = "linux", target_os = "android")
+))]
+#[test]
+fn set_title_and_oom_adj_writes_score() {
+    let _lock = test_lock();
+    let read = || std::fs::read_to_string("/proc/self/oom_score_adj").unwrap();
+
+    // Raising the score is always allowed.
+    let raised = (read().trim().parse::<i16>().unwrap() + 1).min(1000);
+    set_title_and_oom_adj("oom sacrificial", raised).unwrap();
+    assert_eq!(get_title().unwrap(), "oom sacrificial");
+    assert_eq!(read().trim(), raised.to_string());
+
+    assert!(set_title_and_oom_adj("out of range", 1001).is_err());
+    assert_eq!(get_title().unwrap(), "out of range");
+    reset_title();
+}
+
+#[cfg(all(feature = "std", not(feature = "disabled")))]
+#[test]
+fn export_title_env_round_trips() {
+    let _lock = test_lock();
+    set_title("carried over");
+    export_title_env();
+    assert_eq!(std::env::var_os(TITLE_ENV_VAR).unwrap(), "carried over");
+
+    reset_title();
+    export_title_env();
+    assert_eq!(std::env::var_os(TITLE_ENV_VAR), None);
+}
+
+#[cfg(all(feature = "std", not(feature = "disabled")))]
+#[test]
+fn export_title_env_skips_rejected_title() {
+    let _lock = test_lock();
+    set_title("still showing");
+    assert!(set_title_checked("x\0y").is_err());
+    export_title_env();
+    assert_eq!(std::env::var_os(TITLE_ENV_VAR).unwrap(), "still showing");
+
+    reset_title();
+    std::env::remove_var(TITLE_ENV_VAR);
+}
+
+#[cfg(all(
+    feature = "std",
+    not(any(feature = "disabled", feature = "mock")),
+    any(target_os = "linux", target_os = "android")
+))]
+#[test]
+fn init_from_env_var_sets_title() {
+    let _lock = test_lock();
+    std::env::set_var("PROCTITLE_TEST_TITLE", "replica-7");
+    assert!(init_from_env_var("PROCTITLE_TEST_TITLE"));
+    assert_eq!(get_title().unwrap(), "replica-7");
+
+    std::env::set_var("PROCTITLE_TEST_TITLE", "");
+    assert!(!init_from_env_var("PROCTITLE_TEST_TITLE"));
+    std::env::remove_var("PROCTITLE_TEST_TITLE");
+    assert!(!init_from_env_var("PROCTITLE_TEST_TITLE"));
+    assert_eq!(get_title().unwrap(), "replica-7");
+}
+
+#[cfg(all(
+    feature = "std",
+    not(any(feature = "disabled", feature = "mock")),
+    any(target_os = "linux", target_os = "android")
+))]
+#[test]
+fn set_title_raw_sets_title() {
+    let _lock = test_lock();
+    set_title_raw(CStr::from_bytes_with_nul(b"1048576 packets\0").unwrap());
+    assert_eq!(get_title().unwrap(), "1048576 packets");
+}
+
+#[cfg(all(
+    feature = "std",
+    not(any(feature = "disabled", feature = "mock")),
+    any(target_os = "linux", target_os = "android")
+))]
+#[test]
+fn set_title_cstr_sets_full_title() {
+    let _lock = test_lock();
+    set_title_cstr(CStr::from_bytes_with_nul(b"a title longer than the comm name\0").unwrap());
+    assert_eq!(get_title().unwrap(), "a title longer than the comm name");
+}
+
+#[cfg(all(
+    feature = "std",
+    not(any(feature = "disabled", feature = "mock")),
+    any(target_os = "linux", target_os = "android")
+))]
+#[test]
+fn set_title_segments_joins_parts() {
+    let _lock = test_lock();
+    set_title_segments(": ", ["myapp", "worker 3", "idle"]);
+    assert_eq!(get_title().unwrap(), "myapp: worker 3: idle");
+    set_title_segments(" ", vec![OsString::from("lonely")]);
+    assert_eq!(get_title().unwrap(), "lonely");
+    reset_title();
+    let original = get_title().unwrap();
+    set_title_segments(": ", Vec::<&str>::new());
+    assert_eq!(get_title().unwrap(), original);
+}
+
+#[cfg(all(
+    feature = "std",
+    not(any(feature = "disabled", feature = "mock")),
+    any(target_os = "linux", target_os = "android")
+))]
+#[test]
+fn set_title_suffix_prepends_base() {
+    // The base is shared by the whole process, so keep all its tests here
+    let _lock = test_lock();
+    set_title_suffix("warming up");
+    let exe = std::env::current_exe().unwrap();
+    let exe = exe.file_name().unwrap().to_str().unwrap();
+    assert_eq!(get_title().unwrap(), &format!("{}: warming up", exe)[..]);
+
+    set_base_title("postgres");
+    set_title_suffix("autovacuum worker");
+    assert_eq!(get_title().unwrap(), "postgres: autovacuum worker");
+
+    set_base_title("");
+    set_title_suffix("headless");
+    assert_eq!(get_title().unwrap(), "headless");
+
+    init();
+    set_title_suffix("initialised");
+    assert_eq!(get_title().unwrap(), &format!("{}: initialised", exe)[..]);
+
+    set_base_title("custom");
+    init();
+    set_title_suffix("again");
+    assert_eq!(get_title().unwrap(), "custom: again");
+}
+
+#[cfg(all(
+    feature = "std",
+    not(any(feature = "disabled", feature = "mock")),
+    any(target_os = "linux", target_os = "android")
+))]
+#[test]
+fn replace_title_returns_previous_title() {
+    let _lock = test_lock();
+    set_title("Before");
+    assert_eq!(replace_title("After").unwrap(), "Before");
+    assert_eq!(get_title().unwrap(), "After");
+
+    reset_title();
+    assert_eq!(replace_title("Untitled before"), None);
+    reset_title();
+}
+
+#[cfg(all(
+    feature = "std",
+    not(any(feature = "disabled", feature = "mock")),
+    any(target_os = "linux", target_os = "android")
+))]
+#[test]
+fn replace_title_skips_rejected_title() {
+    let _lock = test_lock();
+    set_title("Showing");
+    assert!(set_title_checked("a\0b").is_err());
+    assert_eq!(replace_title("next").unwrap(), "Showing");
+    reset_title();
+}
+
+#[cfg(all(feature = "std", not(feature = "mock")))]
+#[test]
+fn set_title_checked_reports_truncation() {
+    let _lock = test_lock();
+    let max = match max_title_len() {
+        Some(max) => max,
+        None => return,
+    };
+
+    match set_title_checked("w".repeat(max + 5)) {
+        Err(TitleError::Truncated { written, requested }) => {
+            assert_eq!((written, requested), (max, max + 5));
+        }
+        other => panic!("unexpected result: {:?}", other),
+    }
+    set_title_checked("w".repeat(max)).unwrap();
+}
+
+#[cfg(feature = "std")]
+#[test]
+fn current_title_formats_live_title() {
+    let _lock = test_lock();
+    set_title("On display");
+    let shown = CurrentTitle.to_string();
+    match get_title() {
+        Some(title) => assert_eq!(shown, title.to_string_lossy()),
+        None if !is_supported() => assert_eq!(shown, "<unsupported>"),
+        None => assert_eq!(shown, "<unknown>"),
+    }
+
+    assert_eq!(
+        format!("{:?}", CurrentTitle),
+        format!(
+            "CurrentTitle {{ platform: {:?}, supported: {} }}",
+            std::env::consts::OS,
+            is_supported()
+        )
+    );
+}
+
+#[cfg(feature = "std")]
+#[test]
+fn platform_info_matches_support() {
+    let info = platform_info();
+    assert_eq!(info.os, std::env::consts::OS);
+    assert_eq!(info.max_len, max_title_len());
+    assert_eq!(info.mechanism == Mechanism::Noop, !is_supported());
+}
+
+#[cfg(all(
+    feature = "std",
+    not(any(feature = "disabled", feature = "mock")),
+    any(target_os = "linux", target_os = "android")
+))]
+#[test]
+fn describe_mentions_comm_limit() {
+    assert!(platform_info()
+        .mechanism
+        .describe()
+        .contains("PR_SET_NAME), as shown by top, limited to 15 bytes"));
+}
+
+#[cfg(feature = "std")]
+#[test]
+fn set_title_checked_rejects_interior_nul() {
+    let _lock = test_lock();
+    match set_title_checked("Squeak\0squeak") {
+        Err(TitleError::InteriorNul) | Err(TitleError::Unsupported) => (),
+        other => panic!("unexpected result: {:?}", other),
+    }
+}
+
+#[cfg(all(
+    feature = "std",
+    not(any(feature = "disabled", feature = "mock")),
+    any(target_os = "linux", target_os = "android")
+))]
+#[test]
+fn set_title_scoped_restores_previous_title() {
+    let _lock = test_lock();
+    set_title("Make it so");
+    {
+        let _guard = set_title_scoped("Engage");
+        assert_eq!(get_title().unwrap(), "Engage");
+    }
+    assert_eq!(get_title().unwrap(), "Make it so");
+}
+
+#[cfg(all(
+    feature = "std",
+    not(any(feature = "disabled", feature = "mock")),
+    any(target_os = "linux", target_os = "android")
+))]
+#[test]
+fn set_title_scoped_restores_original_title() {
+    let _lock = test_lock();
+    reset_title();
+    let comm = std::fs::read("/proc/self/comm").unwrap();
+    let cmdline = std::fs::read("/proc/self/cmdline").unwrap();
+
+    drop(set_title_scoped("probe"));
+    assert_eq!(std::fs::read("/proc/self/comm").unwrap(), comm);
+    assert_eq!(std::fs::read("/proc/self/cmdline").unwrap(), cmdline);
+}
+
+#[cfg(all(
+    feature = "std",
+    not(any(feature = "disabled", feature = "mock")),
+    any(target_os = "linux", target_os = "android")
+))]
+#[test]
+fn set_title_scoped_skips_rejected_title() {
+    let _lock = test_lock();
+    set_title("Showing");
+    assert!(set_title_checked("a\0b").is_err());
+    drop(set_title_scoped("probe"));
+    assert_eq!(get_title().unwrap(), "Showing");
+    reset_title();
+}
+
+#[cfg(all(
+    feature = "std",
+    not(any(feature = "disabled", feature = "mock")),
+    any(target_os = "linux", target_os = "android")
+))]
+#[test]
+fn with_title_restores_previous_title_on_panic() {
+    let _lock = test_lock();
+    set_title("Idle");
+    assert_eq!(with_title("Busy", || get_title().unwrap()), "Busy");
+    assert_eq!(get_title().unwrap(), "Idle");
+
+    let result = std::panic::catch_unwind(|| with_title("Doomed", || panic!("oops")));
+    assert!(result.is_err());
+    assert_eq!(get_title().unwrap(), "Idle");
+}
+
+#[cfg(all(
+    feature = "std",
+    not(any(feature = "disabled", feature = "mock")),
+    any(target_os = "linux", target_os = "android")
+))]
+#[test]
+fn pop_title_restores_pushed_titles() {
+    // The stack is shared by the whole process, so keep all its tests here
+    let _lock = test_lock();
+    pop_title();
+    reset_title();
+    let original = get_title().unwrap();
+    push_title("loading");
+    push_title("loading: stage 2");
+    assert_eq!(get_title().unwrap(), "loading: stage 2");
+    pop_title();
+    assert_eq!(get_title().unwrap(), "loading");
+    pop_title();
+    assert_eq!(get_title().unwrap(), original);
+    pop_title();
+    assert_eq!(get_title().unwrap(), original);
+}
+
+#[cfg(feature = "std")]
+#[test]
+fn is_supported_matches_set_title_checked() {
+    let _lock = test_lock();
+    assert_eq!(
+        is_supported(),
+        !matches!(
+            set_title_checked("Anybody there?"),
+            Err(TitleError::Unsupported)
+        )
+    );
 }
@@ -0,0 +1,67 @@
+use std::io;
+use std::os::unix::net::UnixDatagram;
+
+/// Report a status line to systemd with `sd_notify(3)`'s `STATUS=`, shown by
+/// `systemctl status`.
+///
+/// Does nothing unless `$NOTIFY_SOCKET` is set, as it is for services with
+/// `Type=notify` or `NotifyAccess=`.  Newlines are replaced with spaces, so
+/// they can't be taken as further assignments.
+///
+/// Requires the `systemd` feature.
+pub fn set_status<T: AsRef<str>>(status: T) {
+    let _ = notify(&format!("STATUS={}\n", status.as_ref().replace('\n', " ")));
+}
+
+/// Report a status line to systemd, as [`set_status`], and also use it as the
+/// process title.
+///
+/// Requires the `systemd` feature.
+pub fn set_status_and_title<T: AsRef<str>>(status: T) {
+    set_status(&status);
+    crate::set_title(status.as_ref());
+}
+
+fn notify(message: &str) -> io::Result<()> {
+    let path = match std::env::var_os("NOTIFY_SOCKET") {
+        Some(path) => path,
+        None => return Ok(()),
+    };
+
+    let socket = UnixDatagram::unbound()?;
+
+    // Names starting with @ are in Linux's abstract namespace.
+    #[cfg(target_os = "linux")]
+    {
+        use std::os::linux::net::SocketAddrExt;
+        use std::os::unix::ffi::OsStrExt;
+        use std::os::unix::net::SocketAddr;
+
+        if let Some(name) = path.as_bytes().strip_prefix(b"@") {
+            let addr = SocketAddr::from_abstract_name(name)?;
+            socket.send_to_addr(message.as_bytes(), &addr)?;
+            return Ok(());
+        }
+    }
+
+    socket.send_to(message.as_bytes(), path)?;
+    Ok(())
+}
+
+#[test]
+fn set_status_notifies_socket() {
+    let _lock = crate::test_lock();
+    let path = std::env::temp_dir().join(format!("proctitle-{}.sock", std::process::id()));
+    let _ = std::fs::remove_file(&path);
+    let listener = UnixDatagram::bind(&path).unwrap();
+
+    std::env::set_var("NOTIFY_SOCKET", &path);
+    set_status("Reticulating\nsplines");
+    std::env::remove_var("NOTIFY_SOCKET");
+    set_status("Nobody listening");
+
+    let mut buf = [0u8; 64];
+    let len = listener.recv(&mut buf).unwrap();
+    let _ = std::fs::remove_file(&path);
+    assert_eq!(&buf[..len], b"STATUS=Reticulating splines\n");
+}
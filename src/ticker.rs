@@ -0,0 +1,110 @@
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Condvar, Mutex};
+use std::thread::{self, JoinHandle};
+use std::time::Duration;
+
+struct Shared {
+    stop: AtomicBool,
+    lock: Mutex<()>,
+    wakeup: Condvar,
+}
+
+/// A background thread updating the process title, which stops when dropped.
+///
+/// Returned by [`spawn_title_ticker`].
+#[must_use = "the ticker stops as soon as it's dropped"]
+pub struct TitleTicker {
+    shared: Arc<Shared>,
+    thread: Option<JoinHandle<()>>,
+}
+
+impl std::fmt::Debug for TitleTicker {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("TitleTicker").finish_non_exhaustive()
+    }
+}
+
+impl Drop for TitleTicker {
+    fn drop(&mut self) {
+        {
+            let _lock = self.shared.lock.lock().unwrap_or_else(|e| e.into_inner());
+            self.shared.stop.store(true, Ordering::SeqCst);
+        }
+        self.shared.wakeup.notify_all();
+
+        if let Some(thread) = self.thread.take() {
+            let _ = thread.join();
+        }
+    }
+}
+
+/// Spawn a thread setting the process title to the result of `f` every
+/// `interval`, starting straight away, until the returned handle is dropped.
+///
+/// ```
+/// use std::time::Duration;
+///
+/// let _ticker = proctitle::spawn_title_ticker(Duration::from_secs(1), || {
+///     let up = proctitle::since_first_title().unwrap_or_default();
+///     format!("myapp: up {}s", up.as_secs())
+/// });
+/// ```
+pub fn spawn_title_ticker<F>(interval: Duration, mut f: F) -> TitleTicker
+where
+    F: FnMut() -> String + Send + 'static,
+{
+    let shared = Arc::new(Shared {
+        stop: AtomicBool::new(false),
+        lock: Mutex::new(()),
+        wakeup: Condvar::new(),
+    });
+
+    let thread = {
+        let shared = Arc::clone(&shared);
+        thread::Builder::new()
+            .name("proctitle-ticker".into())
+            .spawn(move || {
+                while !shared.stop.load(Ordering::SeqCst) {
+                    crate::set_title(f());
+
+                    let lock = shared.lock.lock().unwrap_or_else(|e| e.into_inner());
+                    let _ = shared.wakeup.wait_timeout_while(lock, interval, |_| {
+                        !shared.stop.load(Ordering::SeqCst)
+                    });
+                }
+            })
+            .expect("spawn title ticker")
+    };
+
+    TitleTicker {
+        shared,
+        thread: Some(thread),
+    }
+}
+
+#[test]
+fn title_ticker_stops_promptly() {
+    use std::sync::atomic::AtomicUsize;
+    use std::time::Instant;
+
+    let _lock = crate::test_lock();
+    let ticks = Arc::new(AtomicUsize::new(0));
+    let ticker = {
+        let ticks = Arc::clone(&ticks);
+        spawn_title_ticker(Duration::from_secs(3600), move || {
+            format!("tick {}", ticks.fetch_add(1, Ordering::SeqCst))
+        })
+    };
+
+    while ticks.load(Ordering::SeqCst) == 0 {
+        thread::yield_now();
+    }
+
+    let start = Instant::now();
+    drop(ticker);
+    assert!(
+        start.elapsed() < Duration::from_secs(60),
+        "ticker slow to stop"
+    );
+    assert_eq!(ticks.load(Ordering::SeqCst), 1);
+}
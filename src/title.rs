@@ -0,0 +1,215 @@
+use std::ffi::{OsStr, OsString};
+
+/// A process title built from a base and a list of segments, joined with a
+/// separator.
+///
+/// The joined title is kept between calls to [`apply`](Title::apply), so a
+/// title built once at startup can have individual segments changed in a loop
+/// without starting from scratch each time.
+///
+/// ```
+/// let mut title = proctitle::Title::new();
+/// title.base("myapp").segment("worker 3").segment("idle").apply();
+///
+/// for job in &["fetching", "parsing"] {
+///     title.set_segment(1, job).apply();
+/// }
+/// ```
+#[derive(Debug, Clone)]
+pub struct Title {
+    base: OsString,
+    segments: Vec<OsString>,
+    separator: String,
+    pad: Option<(usize, char)>,
+    joined: OsString,
+    dirty: bool,
+}
+
+impl Default for Title {
+    fn default() -> Self {
+        Self {
+            base: OsString::new(),
+            segments: Vec::new(),
+            separator: String::from(": "),
+            pad: None,
+            joined: OsString::new(),
+            dirty: true,
+        }
+    }
+}
+
+impl Title {
+    /// An empty title, with `": "` between its parts.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Set the part the title starts with, such as the program name.
+    pub fn base(&mut self, base: &str) -> &mut Self {
+        self.base.clear();
+        self.base.push(base);
+        self.dirty = true;
+        self
+    }
+
+    /// Add a segment to the end of the title.
+    pub fn segment<S: AsRef<OsStr>>(&mut self, segment: S) -> &mut Self {
+        self.segments.push(segment.as_ref().to_owned());
+        self.dirty = true;
+        self
+    }
+
+    /// Replace the segment at `index`, counting from 0 after the base.
+    ///
+    /// # Panics
+    ///
+    /// If there's no segment at `index`.
+    pub fn set_segment<S: AsRef<OsStr>>(&mut self, index: usize, segment: S) -> &mut Self {
+        let old = &mut self.segments[index];
+        if old.as_os_str() != segment.as_ref() {
+            old.clear();
+            old.push(segment);
+            self.dirty = true;
+        }
+        self
+    }
+
+    /// Remove all the segments, leaving the base.
+    pub fn clear_segments(&mut self) -> &mut Self {
+        self.segments.clear();
+        self.dirty = true;
+        self
+    }
+
+    /// Set what goes between the parts of the title.
+    pub fn separator(&mut self, separator: &str) -> &mut Self {
+        self.separator = separator.to_owned();
+        self.dirty = true;
+        self
+    }
+
+    /// Pad the title out to `width` characters with `pad`, so it keeps the
+    /// same length as the numbers in it change, or `None` not to.
+    ///
+    /// Padding stops short of [`max_title_len`](crate::max_title_len), so it
+    /// never causes a title to be truncated.  An empty title isn't padded.
+    ///
+    /// ```
+    /// let mut title = proctitle::Title::new();
+    /// title.base("myapp").segment("9 jobs").pad(Some((15, ' ')));
+    /// assert_eq!(title.as_os_str(), "myapp: 9 jobs  ");
+    /// ```
+    pub fn pad(&mut self, pad: Option<(usize, char)>) -> &mut Self {
+        self.pad = pad;
+        self.dirty = true;
+        self
+    }
+
+    /// The title as it stands, joined only if something has changed.
+    pub fn as_os_str(&mut self) -> &OsStr {
+        if self.dirty {
+            self.joined.clear();
+            let parts = Some(&self.base)
+                .filter(|base| !base.is_empty())
+                .into_iter()
+                .chain(&self.segments);
+            for (i, part) in parts.enumerate() {
+                if i > 0 {
+                    self.joined.push(&self.separator);
+                }
+                self.joined.push(part);
+            }
+            if let Some((width, pad)) = self.pad {
+                self.pad_joined(width, pad);
+            }
+            self.dirty = false;
+        }
+
+        &self.joined
+    }
+
+    fn pad_joined(&mut self, width: usize, pad: char) {
+        if self.joined.is_empty() {
+            return;
+        }
+
+        // Characters for the width, but the platform's units for the limit.
+        let chars = self.joined.to_string_lossy().chars().count();
+        let (mut len, pad_len) = if cfg!(windows) {
+            (
+                self.joined.to_string_lossy().encode_utf16().count(),
+                pad.len_utf16(),
+            )
+        } else {
+            (self.joined.len(), pad.len_utf8())
+        };
+        let max = crate::max_title_len().unwrap_or(usize::MAX);
+
+        let mut buf = [0; 4];
+        let pad = &*pad.encode_utf8(&mut buf);
+        for _ in chars..width {
+            if len + pad_len > max {
+                break;
+            }
+            self.joined.push(pad);
+            len += pad_len;
+        }
+    }
+
+    /// Set the process title to this title.
+    pub fn apply(&mut self) -> &mut Self {
+        crate::set_title(self.as_os_str());
+        self
+    }
+}
+
+#[test]
+fn title_joins_parts() {
+    let mut title = Title::new();
+    assert_eq!(title.as_os_str(), "");
+    title.base("myapp").segment("worker 3").segment("idle");
+    assert_eq!(title.as_os_str(), "myapp: worker 3: idle");
+    title.set_segment(1, "busy");
+    assert_eq!(title.as_os_str(), "myapp: worker 3: busy");
+    title.separator(" | ").base("");
+    assert_eq!(title.as_os_str(), "worker 3 | busy");
+    title.clear_segments();
+    assert_eq!(title.as_os_str(), "");
+}
+
+#[test]
+fn title_pads_to_width() {
+    let mut title = Title::new();
+    title.base("up").pad(Some((6, '.')));
+    assert_eq!(title.as_os_str(), "up....");
+    title.segment("100%");
+    assert_eq!(title.as_os_str(), "up: 100%");
+    title.base("née").clear_segments();
+    assert_eq!(title.as_os_str(), "née...");
+    title.base("").pad(Some((4, ' ')));
+    assert_eq!(title.as_os_str(), "");
+}
+
+#[cfg(all(
+    not(any(feature = "disabled", feature = "mock")),
+    any(target_os = "linux", target_os = "android")
+))]
+#[test]
+fn title_padding_stops_at_max_len() {
+    let _lock = crate::test_lock();
+    let max = crate::max_title_len().unwrap();
+    let mut title = Title::new();
+    title.base("wide").pad(Some((max + 100, ' ')));
+    assert_eq!(title.as_os_str().len(), max);
+}
+
+#[cfg(all(
+    not(any(feature = "disabled", feature = "mock")),
+    any(target_os = "linux", target_os = "android")
+))]
+#[test]
+fn title_apply_sets_title() {
+    let _lock = crate::test_lock();
+    Title::new().base("myapp").segment("ready").apply();
+    assert_eq!(crate::get_title().unwrap(), "myapp: ready");
+}
@@ -0,0 +1,128 @@
+use std::ffi::OsString;
+
+use tracing_core::span::Id;
+use tracing_core::Subscriber;
+use tracing_subscriber::layer::{Context, Layer};
+use tracing_subscriber::registry::LookupSpan;
+
+/// A [`tracing_subscriber::Layer`] which sets the process title to the name of
+/// the most recently entered span, restoring the previous title on exit.
+///
+/// ```
+/// use tracing_subscriber::prelude::*;
+///
+/// let subscriber = tracing_subscriber::registry().with(proctitle::TitleLayer::new());
+/// tracing::subscriber::with_default(subscriber, || {
+///     let _span = tracing::info_span!("compacting").entered();
+///     // The process is now titled "compacting"
+/// });
+/// ```
+///
+/// Requires the `tracing` feature.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct TitleLayer {
+    _priv: (),
+}
+
+impl TitleLayer {
+    /// Create a new layer.
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+/// The title a span replaced when it was entered, as from `swap_title()`:
+/// `Some(None)` for the original title, and `None` if titles were silenced.
+struct PreviousTitle(Option<Option<OsString>>);
+
+impl<S> Layer<S> for TitleLayer
+where
+    S: Subscriber + for<'a> LookupSpan<'a>,
+{
+    fn on_enter(&self, id: &Id, ctx: Context<'_, S>) {
+        if let Some(span) = ctx.span(id) {
+            let previous = crate::swap_title(span.name().as_ref());
+            span.extensions_mut().replace(PreviousTitle(previous));
+        }
+    }
+
+    fn on_exit(&self, id: &Id, ctx: Context<'_, S>) {
+        if let Some(span) = ctx.span(id) {
+            match span.extensions_mut().remove() {
+                Some(PreviousTitle(Some(Some(previous)))) => crate::set_title(previous),
+                Some(PreviousTitle(Some(None))) => crate::reset_title(),
+                _ => (),
+            }
+        }
+    }
+}
+
+#[cfg(all(
+    not(any(feature = "disabled", feature = "mock")),
+    any(target_os = "linux", target_os = "android")
+))]
+#[test]
+fn title_layer_follows_entered_spans() {
+    use tracing_subscriber::layer::SubscriberExt;
+
+    let _lock = crate::test_lock();
+    crate::set_title("Waiting");
+
+    let subscriber = tracing_subscriber::registry().with(TitleLayer::new());
+    tracing::subscriber::with_default(subscriber, || {
+        let outer = tracing::info_span!("outer").entered();
+        assert_eq!(crate::get_title().unwrap(), "outer");
+        {
+            let _inner = tracing::info_span!("inner").entered();
+            assert_eq!(crate::get_title().unwrap(), "inner");
+        }
+        assert_eq!(crate::get_title().unwrap(), "outer");
+        drop(outer);
+    });
+
+    assert_eq!(crate::get_title().unwrap(), "Waiting");
+}
+
+#[cfg(all(
+    not(any(feature = "disabled", feature = "mock")),
+    any(target_os = "linux", target_os = "android")
+))]
+#[test]
+fn title_layer_restores_original_title() {
+    use tracing_subscriber::layer::SubscriberExt;
+
+    let _lock = crate::test_lock();
+    crate::reset_title();
+    let comm = std::fs::read("/proc/self/comm").unwrap();
+    let cmdline = std::fs::read("/proc/self/cmdline").unwrap();
+
+    let subscriber = tracing_subscriber::registry().with(TitleLayer::new());
+    tracing::subscriber::with_default(subscriber, || {
+        let _span = tracing::info_span!("probe").entered();
+        assert_eq!(crate::get_title().unwrap(), "probe");
+    });
+
+    assert_eq!(std::fs::read("/proc/self/comm").unwrap(), comm);
+    assert_eq!(std::fs::read("/proc/self/cmdline").unwrap(), cmdline);
+}
+
+#[cfg(all(
+    not(any(feature = "disabled", feature = "mock")),
+    any(target_os = "linux", target_os = "android")
+))]
+#[test]
+fn title_layer_skips_rejected_title() {
+    use tracing_subscriber::layer::SubscriberExt;
+
+    let _lock = crate::test_lock();
+    crate::set_title("Showing");
+    assert!(crate::set_title_checked("a\0b").is_err());
+
+    let subscriber = tracing_subscriber::registry().with(TitleLayer::new());
+    tracing::subscriber::with_default(subscriber, || {
+        let _span = tracing::info_span!("probe").entered();
+    });
+
+    assert_eq!(crate::get_title().unwrap(), "Showing");
+    crate::reset_title();
+}
@@ -0,0 +1,51 @@
+//! Races several threads on the very first title, in a process of its own so
+//! nothing has initialised the argv region beforehand.
+#![cfg(all(
+    feature = "std",
+    not(any(feature = "disabled", feature = "mock")),
+    any(target_os = "linux", target_os = "android")
+))]
+
+use std::sync::{Arc, Barrier};
+use std::thread;
+
+#[test]
+fn first_titles_from_many_threads() {
+    let args: Vec<_> = std::env::args_os().collect();
+    let vars: Vec<_> = std::env::vars_os().collect();
+    let barrier = Arc::new(Barrier::new(8));
+
+    let threads: Vec<_> = (0..8)
+        .map(|i| {
+            let barrier = Arc::clone(&barrier);
+            thread::spawn(move || {
+                // Long enough to make every thread want to relocate environ.
+                let title = format!("racer {} {}", i, "x".repeat(64 * 1024));
+                barrier.wait();
+                proctitle::set_title(title);
+            })
+        })
+        .collect();
+
+    for thread in threads {
+        thread.join().unwrap();
+    }
+
+    let title = proctitle::get_title().unwrap().into_string().unwrap();
+    assert!(title.starts_with("racer "), "unexpected title");
+    assert_eq!(title.len(), proctitle::max_title_len().unwrap());
+
+    let cmdline = std::fs::read("/proc/self/cmdline").unwrap();
+    assert!(cmdline.starts_with(&title.as_bytes()[..20]));
+
+    assert_eq!(
+        std::env::args_os().collect::<Vec<_>>(),
+        args,
+        "argv clobbered"
+    );
+    assert_eq!(
+        std::env::vars_os().collect::<Vec<_>>(),
+        vars,
+        "environment clobbered"
+    );
+}
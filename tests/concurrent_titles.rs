@@ -0,0 +1,63 @@
+//! Hammers the title from many threads at once, in a process of its own so
+//! the title isn't changed under us by any other test.
+#![cfg(all(
+    feature = "std",
+    not(any(feature = "disabled", feature = "mock")),
+    any(target_os = "linux", target_os = "android")
+))]
+
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Barrier};
+use std::thread;
+
+// Each thread's title is a different length made of its own digit, so any
+// interleaving of two writes shows up as a mix of digits or a stray tail.
+fn title(i: usize) -> String {
+    char::from(b'0' + i as u8).to_string().repeat(8 + i * 16)
+}
+
+fn is_whole(bytes: &[u8]) -> bool {
+    let bytes = bytes.split(|&b| b == 0).next().unwrap_or_default();
+    (0..8).any(|i| bytes == title(i).as_bytes())
+}
+
+#[test]
+fn concurrent_titles_never_tear() {
+    proctitle::set_title(title(0));
+
+    let done = Arc::new(AtomicBool::new(false));
+    let barrier = Arc::new(Barrier::new(9));
+
+    let reader = {
+        let (done, barrier) = (Arc::clone(&done), Arc::clone(&barrier));
+        thread::spawn(move || {
+            barrier.wait();
+            while !done.load(Ordering::Relaxed) {
+                let title = proctitle::get_title().unwrap();
+                assert!(is_whole(title.as_encoded_bytes()), "torn title");
+            }
+        })
+    };
+
+    let writers: Vec<_> = (0..8)
+        .map(|i| {
+            let barrier = Arc::clone(&barrier);
+            thread::spawn(move || {
+                let title = title(i);
+                barrier.wait();
+                for _ in 0..2000 {
+                    proctitle::set_title(&title);
+                }
+            })
+        })
+        .collect();
+
+    for writer in writers {
+        writer.join().unwrap();
+    }
+    done.store(true, Ordering::Relaxed);
+    reader.join().unwrap();
+
+    let cmdline = std::fs::read("/proc/self/cmdline").unwrap();
+    assert!(is_whole(&cmdline), "torn cmdline");
+}
@@ -0,0 +1,40 @@
+//! Forks with a title set, in a process of its own so no other test's threads
+//! are caught holding a lock.
+#![cfg(all(
+    feature = "std",
+    not(any(feature = "disabled", feature = "mock")),
+    any(target_os = "linux", target_os = "android")
+))]
+
+#[test]
+fn forked_child_resets_to_title_at_fork() {
+    proctitle::set_title("parent before fork");
+
+    match unsafe { libc::fork() } {
+        -1 => panic!("fork failed: {}", std::io::Error::last_os_error()),
+        0 => {
+            // The child's name comes from the thread that forked it.
+            let comm = std::fs::read("/proc/self/comm").unwrap();
+            proctitle::set_title("child at work");
+            let worked = proctitle::get_title().unwrap() == "child at work";
+            proctitle::reset_title();
+
+            let ok = worked
+                && proctitle::get_title().unwrap() == "parent before fork"
+                && std::fs::read("/proc/self/comm").unwrap() == comm;
+            unsafe { libc::_exit(if ok { 0 } else { 1 }) };
+        }
+        child => {
+            let mut status = 0;
+            assert_eq!(unsafe { libc::waitpid(child, &mut status, 0) }, child);
+            assert!(libc::WIFEXITED(status), "child didn't exit");
+            assert_eq!(libc::WEXITSTATUS(status), 0, "child saw the wrong title");
+        }
+    }
+
+    // The parent's original is still the command line it started with.
+    let args: Vec<_> = std::env::args_os().collect();
+    proctitle::reset_title();
+    let cmdline = std::fs::read("/proc/self/cmdline").unwrap();
+    assert!(cmdline.starts_with(args[0].to_str().unwrap().as_bytes()));
+}
@@ -0,0 +1,50 @@
+//! Makes the process arguments read-only before the first title, in a forked
+//! child of a process of its own, so nothing has claimed them beforehand.
+#![cfg(all(
+    feature = "std",
+    not(any(feature = "disabled", feature = "mock")),
+    target_os = "linux"
+))]
+
+/// Where the kernel says the process arguments are, from /proc/self/stat.
+fn arg_range() -> (usize, usize) {
+    let stat = std::fs::read_to_string("/proc/self/stat").unwrap();
+    let fields: Vec<&str> = stat[stat.rfind(')').unwrap() + 1..]
+        .split_whitespace()
+        .collect();
+    // arg_start and arg_end are fields 48 and 49, counting from 1 before the
+    // two we skipped.
+    (fields[45].parse().unwrap(), fields[46].parse().unwrap())
+}
+
+#[test]
+fn short_titles_report_readonly_argv() {
+    let (start, end) = arg_range();
+    let page = unsafe { libc::sysconf(libc::_SC_PAGESIZE) } as usize;
+    let start = start & !(page - 1);
+
+    match unsafe { libc::fork() } {
+        -1 => panic!("fork failed: {}", std::io::Error::last_os_error()),
+        0 => {
+            // Only the forking thread survives in the child, so nothing is
+            // running on the main thread's stack beneath the arguments.
+            if unsafe { libc::mprotect(start as *mut _, end - start, libc::PROT_READ) } != 0 {
+                unsafe { libc::_exit(2) };
+            }
+
+            let ok = proctitle::set_title_checked("short").is_ok()
+                && proctitle::platform_info().mechanism == proctitle::Mechanism::Prctl
+                && std::fs::read("/proc/self/comm").unwrap() == b"short\n";
+            unsafe { libc::_exit(if ok { 0 } else { 1 }) };
+        }
+        child => {
+            let mut status = 0;
+            assert_eq!(unsafe { libc::waitpid(child, &mut status, 0) }, child);
+            assert!(
+                libc::WIFEXITED(status),
+                "child crashed writing read-only argv"
+            );
+            assert_eq!(libc::WEXITSTATUS(status), 0, "fallback wasn't reported");
+        }
+    }
+}
@@ -0,0 +1,74 @@
+//! Blocks prctl(PR_SET_NAME) with a seccomp filter, in a forked child so the
+//! filter can't outlive the test.
+#![cfg(all(
+    feature = "std",
+    not(any(feature = "disabled", feature = "mock")),
+    target_os = "linux"
+))]
+
+/// Fail prctl(PR_SET_NAME) with EPERM, allowing everything else.
+fn block_set_name() -> bool {
+    const NR: u32 = 0;
+    const ARG0: u32 = 16;
+
+    let stmt = |code: u32, k: u32| libc::sock_filter {
+        code: code as u16,
+        jt: 0,
+        jf: 0,
+        k,
+    };
+    let jeq = |k: u32, jf: u8| libc::sock_filter {
+        code: (libc::BPF_JMP | libc::BPF_JEQ | libc::BPF_K) as u16,
+        jt: 0,
+        jf,
+        k,
+    };
+
+    let filter = [
+        stmt(libc::BPF_LD | libc::BPF_W | libc::BPF_ABS, NR),
+        jeq(libc::SYS_prctl as u32, 3),
+        stmt(libc::BPF_LD | libc::BPF_W | libc::BPF_ABS, ARG0),
+        jeq(libc::PR_SET_NAME as u32, 1),
+        stmt(
+            libc::BPF_RET | libc::BPF_K,
+            libc::SECCOMP_RET_ERRNO | libc::EPERM as u32,
+        ),
+        stmt(libc::BPF_RET | libc::BPF_K, libc::SECCOMP_RET_ALLOW),
+    ];
+    let prog = libc::sock_fprog {
+        len: filter.len() as u16,
+        filter: filter.as_ptr() as *mut _,
+    };
+
+    unsafe {
+        libc::prctl(libc::PR_SET_NO_NEW_PRIVS, 1, 0, 0, 0) == 0
+            && libc::prctl(libc::PR_SET_SECCOMP, libc::SECCOMP_MODE_FILTER, &prog) == 0
+    }
+}
+
+#[test]
+fn blocked_prctl_is_reported() {
+    match unsafe { libc::fork() } {
+        -1 => panic!("fork failed: {}", std::io::Error::last_os_error()),
+        0 => {
+            if !block_set_name() {
+                // No seccomp here, so nothing to test.
+                unsafe { libc::_exit(2) };
+            }
+
+            let ok = match proctitle::set_title_checked("sandboxed") {
+                Err(proctitle::TitleError::Os(e)) => e.raw_os_error() == Some(libc::EPERM),
+                _ => false,
+            };
+            // The infallible version carries on regardless.
+            proctitle::set_title("still sandboxed");
+            unsafe { libc::_exit(if ok { 0 } else { 1 }) };
+        }
+        child => {
+            let mut status = 0;
+            assert_eq!(unsafe { libc::waitpid(child, &mut status, 0) }, child);
+            assert!(libc::WIFEXITED(status), "child didn't exit");
+            assert_ne!(libc::WEXITSTATUS(status), 1, "EPERM wasn't reported");
+        }
+    }
+}
@@ -0,0 +1,81 @@
+//! Sets terminal titles with stdout on a pseudo-terminal, in a forked child,
+//! to see which escape sequences reach the terminal.
+#![cfg(all(feature = "std", not(feature = "disabled"), target_os = "linux"))]
+
+use std::ffi::CStr;
+
+/// Read whatever the terminal has been sent, waiting a little for the line
+/// discipline to pass it through.
+fn drain(master: libc::c_int) -> Vec<u8> {
+    let mut output = Vec::new();
+    let mut buf = [0u8; 256];
+    loop {
+        let mut fd = libc::pollfd {
+            fd: master,
+            events: libc::POLLIN,
+            revents: 0,
+        };
+        if unsafe { libc::poll(&mut fd, 1, 200) } <= 0 {
+            return output;
+        }
+        match unsafe { libc::read(master, buf.as_mut_ptr().cast(), buf.len()) } {
+            n if n > 0 => output.extend_from_slice(&buf[..n as usize]),
+            _ => return output,
+        }
+    }
+}
+
+/// Run `f` in a child with stdout on a new terminal, returning what it wrote.
+fn terminal_output(f: fn()) -> Vec<u8> {
+    let master = unsafe { libc::posix_openpt(libc::O_RDWR | libc::O_NOCTTY) };
+    assert!(master >= 0, "{}", std::io::Error::last_os_error());
+    assert_eq!(unsafe { libc::grantpt(master) }, 0);
+    assert_eq!(unsafe { libc::unlockpt(master) }, 0);
+    let name = unsafe { CStr::from_ptr(libc::ptsname(master)) }.to_owned();
+    // Held open here too, so the terminal outlives the child.
+    let slave = unsafe { libc::open(name.as_ptr(), libc::O_RDWR | libc::O_NOCTTY) };
+    assert!(slave >= 0, "{}", std::io::Error::last_os_error());
+
+    match unsafe { libc::fork() } {
+        -1 => panic!("fork failed: {}", std::io::Error::last_os_error()),
+        0 => {
+            if unsafe { libc::dup2(slave, libc::STDOUT_FILENO) } < 0 {
+                unsafe { libc::_exit(2) };
+            }
+
+            f();
+            unsafe { libc::_exit(0) };
+        }
+        child => {
+            let mut status = 0;
+            assert_eq!(unsafe { libc::waitpid(child, &mut status, 0) }, child);
+            assert!(libc::WIFEXITED(status) && libc::WEXITSTATUS(status) == 0);
+            let output = drain(master);
+            unsafe {
+                libc::close(slave);
+                libc::close(master);
+            }
+            output
+        }
+    }
+}
+
+#[cfg(not(feature = "mock"))]
+#[test]
+fn empty_all_titles_leave_terminal_alone() {
+    let output = terminal_output(|| {
+        proctitle::set_all_titles("");
+        proctitle::set_terminal_title("after");
+    });
+    assert_eq!(output, b"\x1b]0;after\x07");
+}
+
+#[cfg(feature = "mock")]
+#[test]
+fn mock_leaves_terminal_alone() {
+    let output = terminal_output(|| {
+        proctitle::set_terminal_title("mocked");
+        proctitle::set_all_titles("mocked everywhere");
+    });
+    assert_eq!(output, b"");
+}